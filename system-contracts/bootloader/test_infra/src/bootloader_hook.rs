@@ -0,0 +1,175 @@
+use std::fmt;
+
+use zksync_multivm::vm_latest::{HistoryMode, SimpleMemory};
+use zksync_multivm::zk_evm_latest::zkevm_opcode_defs::FatPointer;
+use zksync_types::U256;
+
+use crate::hook::{test_hook_as_int_or_hex, MAX_HOOK_BYTES_LENGTH};
+
+/// Which of the VM hooks of the bootloader itself (`setHook` in bootloader.yul) are logged by the tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HookVerbosity {
+    #[default]
+    Off,
+    /// Only `debugLog` and `debugReturndata`.
+    Debug,
+    /// All the hooks, e.g. the start of the validation or the end of a transaction.
+    All,
+}
+
+impl HookVerbosity {
+    /// Verbosity from the number of `-v` flags.
+    pub fn from_count(count: u8) -> Self {
+        match count {
+            0 => Self::Off,
+            1 => Self::Debug,
+            _ => Self::All,
+        }
+    }
+}
+
+/// VM hook of the bootloader, with the ids of the `VM_HOOK_*` functions in bootloader.yul.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BootloaderHook {
+    // 0
+    AccountValidationEntered,
+    // 1
+    PaymasterValidationEntered,
+    // 2
+    NoValidationEntered,
+    // 3
+    ValidationStepEnded,
+    // 4
+    TxHasEnded,
+    // 5 - message and data of `debugLog`.
+    DebugLog(String, String),
+    // 6 - returndata of the latest call, logged by `debugReturndata`.
+    DebugReturnData(String),
+    // 7
+    CatchNearCall,
+    // 8 - proposed refund, gas spent on pubdata, gas per pubdata byte.
+    AskOperatorForRefund(U256, U256, U256),
+    // 9 - refund given to the user.
+    NotifyAboutRefund(U256),
+    // 10 - whether the transaction succeeded.
+    ExecutionResult(bool),
+    // 11
+    FinalL2StateInfo,
+    // 12
+    PubdataRequested,
+    Unknown(u32),
+}
+
+// Strings of `debugLog` are not always valid UTF-8, e.g. when a word of data is logged as the message.
+fn hook_param_as_string(param: U256) -> String {
+    let mut bytes = [0u8; 32];
+    param.to_big_endian(&mut bytes);
+    let end = bytes
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |pos| pos + 1);
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// Reads the data that the fat pointer points to, e.g. the returndata of a call.
+fn fat_pointer_as_hex<H: HistoryMode>(memory: &SimpleMemory<H>, pointer: U256) -> String {
+    let pointer = FatPointer::from_u256(pointer);
+    let length = pointer.length.saturating_sub(pointer.offset);
+    if length as usize > MAX_HOOK_BYTES_LENGTH {
+        return format!("<{} bytes>", length);
+    }
+    let data = memory.read_unaligned_bytes(
+        pointer.memory_page as usize,
+        (pointer.start + pointer.offset) as usize,
+        length as usize,
+    );
+    format!("0x{}", hex::encode(data))
+}
+
+impl BootloaderHook {
+    pub(crate) fn from_params<H: HistoryMode>(
+        id: u32,
+        params: &[U256],
+        memory: &SimpleMemory<H>,
+    ) -> Self {
+        match id {
+            0 => Self::AccountValidationEntered,
+            1 => Self::PaymasterValidationEntered,
+            2 => Self::NoValidationEntered,
+            3 => Self::ValidationStepEnded,
+            4 => Self::TxHasEnded,
+            5 => Self::DebugLog(
+                hook_param_as_string(params[0]),
+                test_hook_as_int_or_hex(params[1]),
+            ),
+            6 => Self::DebugReturnData(fat_pointer_as_hex(memory, params[0])),
+            7 => Self::CatchNearCall,
+            8 => Self::AskOperatorForRefund(params[0], params[1], params[2]),
+            9 => Self::NotifyAboutRefund(params[0]),
+            10 => Self::ExecutionResult(!params[0].is_zero()),
+            11 => Self::FinalL2StateInfo,
+            12 => Self::PubdataRequested,
+            _ => Self::Unknown(id),
+        }
+    }
+
+    /// Returns whether the hook is logged with the given verbosity.
+    pub(crate) fn is_logged(&self, verbosity: HookVerbosity) -> bool {
+        match self {
+            Self::DebugLog(..) | Self::DebugReturnData(_) => verbosity >= HookVerbosity::Debug,
+            _ => verbosity >= HookVerbosity::All,
+        }
+    }
+}
+
+impl fmt::Display for BootloaderHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AccountValidationEntered => write!(f, "account validation entered"),
+            Self::PaymasterValidationEntered => write!(f, "paymaster validation entered"),
+            Self::NoValidationEntered => write!(f, "validation restrictions lifted"),
+            Self::ValidationStepEnded => write!(f, "validation step ended"),
+            Self::TxHasEnded => write!(f, "transaction has ended"),
+            Self::DebugLog(msg, data) => write!(f, "debugLog {} {}", msg, data),
+            Self::DebugReturnData(data) => write!(f, "debugReturndata {}", data),
+            Self::CatchNearCall => write!(f, "near call panicked"),
+            Self::AskOperatorForRefund(proposed_refund, spent_on_pubdata, gas_per_pubdata) => {
+                write!(
+                    f,
+                    "refund requested: proposed {}, spent on pubdata {}, gas per pubdata {}",
+                    proposed_refund, spent_on_pubdata, gas_per_pubdata
+                )
+            }
+            Self::NotifyAboutRefund(refund) => write!(f, "final refund {}", refund),
+            Self::ExecutionResult(success) => write!(f, "execution result: success {}", success),
+            Self::FinalL2StateInfo => write!(f, "final L2 block requested"),
+            Self::PubdataRequested => write!(f, "pubdata requested"),
+            Self::Unknown(id) => write!(f, "unknown hook {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_param_as_string() {
+        let msg = U256::from("0x72657475726e6461746170747200000000000000000000000000000000000000");
+        assert_eq!(hook_param_as_string(msg), "returndataptr");
+        assert_eq!(hook_param_as_string(U256::zero()), "");
+        // Invalid UTF-8 doesn't fail the test.
+        assert_eq!(hook_param_as_string(U256::MAX).chars().count(), 32);
+    }
+
+    #[test]
+    fn test_is_logged() {
+        let debug_log = BootloaderHook::DebugLog("msg".into(), "1".into());
+        assert!(!debug_log.is_logged(HookVerbosity::Off));
+        assert!(debug_log.is_logged(HookVerbosity::Debug));
+        assert!(!BootloaderHook::TxHasEnded.is_logged(HookVerbosity::Debug));
+        assert!(BootloaderHook::TxHasEnded.is_logged(HookVerbosity::All));
+        assert_eq!(HookVerbosity::from_count(5), HookVerbosity::All);
+        assert_eq!(debug_log.to_string(), "debugLog msg 1");
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use zksync_multivm::interface::tracer::VmExecutionStopReason;
+use zksync_multivm::tracers::dynamic::vm_1_5_2::DynTracer;
+use zksync_multivm::vm_latest::{
+    BootloaderState, HistoryMode, SimpleMemory, VmTracer, ZkSyncVmState,
+};
+use zksync_multivm::zk_evm_latest::tracing::{BeforeExecutionData, VmLocalStateData};
+use zksync_state::interface::{StoragePtr, WriteStorage};
+use zksync_types::BOOTLOADER_ADDRESS;
+
+use crate::debug_info::{
+    innermost_function, labeled_function, load_line_map, parse_assembly, parse_yul_functions,
+    InstructionSource, YulFunction,
+};
+
+/// Number of times each instruction of the bootloader was executed, indexed by the program counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcHits(Vec<u64>);
+
+impl Default for PcHits {
+    fn default() -> Self {
+        Self(vec![0; 1 << 16])
+    }
+}
+
+impl PcHits {
+    pub fn record(&mut self, pc: u16) {
+        self.0[pc as usize] += 1;
+    }
+
+    pub fn get(&self, pc: usize) -> u64 {
+        self.0.get(pc).copied().unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &PcHits) {
+        for (hits, other_hits) in self.0.iter_mut().zip(&other.0) {
+            *hits += other_hits;
+        }
+    }
+}
+
+/// Tracer that records the executed instructions of the bootloader (but not of the contracts it calls).
+/// The hits are added to the shared ones at the end of the run, so that they can be collected across tests.
+pub struct CoverageTracer {
+    hits: PcHits,
+    shared_hits: Arc<Mutex<PcHits>>,
+}
+
+impl CoverageTracer {
+    pub fn new(shared_hits: Arc<Mutex<PcHits>>) -> Self {
+        Self {
+            hits: PcHits::default(),
+            shared_hits,
+        }
+    }
+}
+
+impl<S, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for CoverageTracer {
+    fn before_execution(
+        &mut self,
+        state: VmLocalStateData<'_>,
+        _data: BeforeExecutionData,
+        _memory: &SimpleMemory<H>,
+        _storage: StoragePtr<S>,
+    ) {
+        let frame = &state.vm_local_state.callstack.current;
+        if frame.code_address == BOOTLOADER_ADDRESS {
+            self.hits.record(frame.pc);
+        }
+    }
+}
+
+impl<S: WriteStorage, H: HistoryMode> VmTracer<S, H> for CoverageTracer {
+    fn after_vm_execution(
+        &mut self,
+        _state: &mut ZkSyncVmState<S, H>,
+        _bootloader_state: &BootloaderState,
+        _stop_reason: VmExecutionStopReason,
+    ) {
+        self.shared_hits.lock().unwrap().merge(&self.hits);
+    }
+}
+
+/// Everything that is needed to map the hits of the compiled test bootloader to `bootloader.yul`.
+pub struct CoverageSources {
+    pub source_path: PathBuf,
+    instructions: Vec<InstructionSource>,
+    line_map: Vec<Option<u32>>,
+    functions: Vec<YulFunction>,
+}
+
+impl CoverageSources {
+    /// Loads the assembly of the test bootloader, the map of its lines to `bootloader.yul`, and
+    /// `bootloader.yul` itself.
+    pub fn load(asm_path: &Path, line_map_path: &Path, source_path: &Path) -> Result<Self, String> {
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))
+        };
+        Ok(Self {
+            source_path: source_path.to_path_buf(),
+            instructions: parse_assembly(&read(asm_path)?),
+            line_map: load_line_map(line_map_path)?,
+            functions: parse_yul_functions(&read(source_path)?),
+        })
+    }
+
+    pub fn report(&self, hits: &PcHits) -> CoverageReport {
+        CoverageReport::new(hits, &self.instructions, &self.line_map, &self.functions)
+    }
+}
+
+/// Coverage of a single function of `bootloader.yul`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub first_line: u32,
+    /// Number of times the most executed instruction of the function was executed.
+    /// `None` if the function has no instructions of its own (it was inlined or removed by the optimizer).
+    pub hits: Option<u64>,
+}
+
+/// Line and function coverage of `bootloader.yul`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Hits of the lines of `bootloader.yul` that have instructions. Only present if the assembly has line info.
+    pub lines: BTreeMap<u32, u64>,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl CoverageReport {
+    /// Maps the hits to `bootloader.yul`. `instructions` are the sources of the compiled test bootloader,
+    /// `line_map` maps its lines to the lines of `bootloader.yul`, and `functions` are the functions of
+    /// `bootloader.yul`.
+    ///
+    /// Instructions are attributed to the function containing their line, or (without line info) to the
+    /// function of their assembly label.
+    pub fn new(
+        hits: &PcHits,
+        instructions: &[InstructionSource],
+        line_map: &[Option<u32>],
+        functions: &[YulFunction],
+    ) -> Self {
+        let mut lines = BTreeMap::new();
+        let mut function_hits: Vec<Option<u64>> = vec![None; functions.len()];
+
+        for (pc, instruction) in instructions.iter().enumerate() {
+            let pc_hits = hits.get(pc);
+            let function = match instruction.line {
+                Some(line) => {
+                    // Lines that are not from `bootloader.yul` (e.g. the tests) are not covered.
+                    let line = line_map.get(line as usize - 1).copied().flatten();
+                    line.and_then(|line| {
+                        let line_hits = lines.entry(line).or_insert(0);
+                        *line_hits = pc_hits.max(*line_hits);
+                        innermost_function(functions, line)
+                    })
+                }
+                None => instruction
+                    .function
+                    .as_deref()
+                    .and_then(|label| labeled_function(functions, label)),
+            };
+            if let Some(function) = function {
+                function_hits[function] = Some(pc_hits.max(function_hits[function].unwrap_or(0)));
+            }
+        }
+
+        Self {
+            lines,
+            functions: functions
+                .iter()
+                .zip(function_hits)
+                .map(|(function, hits)| FunctionCoverage {
+                    name: function.name.clone(),
+                    first_line: function.first_line,
+                    hits,
+                })
+                .collect(),
+        }
+    }
+
+    /// Functions that have instructions, but none of them was executed.
+    pub fn uncovered_functions(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions
+            .iter()
+            .filter(|function| function.hits == Some(0))
+    }
+
+    /// Functions without instructions of their own.
+    pub fn missing_functions(&self) -> impl Iterator<Item = &FunctionCoverage> {
+        self.functions
+            .iter()
+            .filter(|function| function.hits.is_none())
+    }
+
+    /// Formats the report as an LCOV tracefile for `source_path`.
+    /// Functions without instructions are left out, as there is nothing to cover.
+    pub fn to_lcov(&self, source_path: &Path) -> String {
+        let mut lcov = String::new();
+        writeln!(lcov, "TN:").unwrap();
+        writeln!(lcov, "SF:{}", source_path.display()).unwrap();
+
+        let functions: Vec<(&FunctionCoverage, u64)> = self
+            .functions
+            .iter()
+            .filter_map(|function| Some((function, function.hits?)))
+            .collect();
+        for (function, _) in &functions {
+            writeln!(lcov, "FN:{},{}", function.first_line, function.name).unwrap();
+        }
+        for (function, hits) in &functions {
+            writeln!(lcov, "FNDA:{},{}", hits, function.name).unwrap();
+        }
+        writeln!(lcov, "FNF:{}", functions.len()).unwrap();
+        writeln!(
+            lcov,
+            "FNH:{}",
+            functions.iter().filter(|(_, hits)| *hits > 0).count()
+        )
+        .unwrap();
+
+        for (line, hits) in &self.lines {
+            writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+        }
+        writeln!(lcov, "LF:{}", self.lines.len()).unwrap();
+        writeln!(
+            lcov,
+            "LH:{}",
+            self.lines.values().filter(|hits| **hits > 0).count()
+        )
+        .unwrap();
+        writeln!(lcov, "end_of_record").unwrap();
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, first_line: u32, last_line: u32) -> YulFunction {
+        YulFunction {
+            name: name.to_string(),
+            first_line,
+            last_line,
+        }
+    }
+
+    fn instruction(function: &str, line: Option<u32>) -> InstructionSource {
+        InstructionSource {
+            function: Some(function.to_string()),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_coverage_report() {
+        // bootloader.yul: `main` at lines 10-30, with `helper` (12-14) nested; `unused` at 40-42; `inlined` at 50-52.
+        let functions = vec![
+            function("main", 10, 30),
+            function("helper", 12, 14),
+            function("unused", 40, 42),
+            function("inlined", 50, 52),
+        ];
+        // The compiled file has 3 lines of tests at the start, then lines 10..=60 of bootloader.yul.
+        let line_map: Vec<Option<u32>> = [None, None, None]
+            .into_iter()
+            .chain((10..=60).map(Some))
+            .collect();
+        let instructions = vec![
+            instruction("main", Some(4)),      // bootloader.yul:10
+            instruction("main", Some(6)),      // bootloader.yul:12, in `helper`
+            instruction("main", Some(1)),      // a test, not in bootloader.yul
+            instruction("unused_3", None),     // no line info
+            instruction("unused_3", Some(34)), // bootloader.yul:40
+        ];
+        let mut hits = PcHits::default();
+        for pc in [0, 0, 1, 2] {
+            hits.record(pc);
+        }
+
+        let report = CoverageReport::new(&hits, &instructions, &line_map, &functions);
+        assert_eq!(report.lines, BTreeMap::from([(10, 2), (12, 1), (40, 0)]));
+        assert_eq!(
+            report
+                .functions
+                .iter()
+                .map(|function| (function.name.as_str(), function.hits))
+                .collect::<Vec<_>>(),
+            vec![
+                ("main", Some(2)),
+                ("helper", Some(1)),
+                ("unused", Some(0)),
+                ("inlined", None)
+            ]
+        );
+        assert_eq!(
+            report
+                .uncovered_functions()
+                .map(|function| function.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["unused"]
+        );
+
+        let lcov = report.to_lcov(Path::new("bootloader.yul"));
+        assert_eq!(
+            lcov,
+            "TN:\nSF:bootloader.yul\n\
+             FN:10,main\nFN:12,helper\nFN:40,unused\n\
+             FNDA:2,main\nFNDA:1,helper\nFNDA:0,unused\nFNF:3\nFNH:2\n\
+             DA:10,2\nDA:12,1\nDA:40,0\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+}
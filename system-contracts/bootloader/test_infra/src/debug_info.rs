@@ -0,0 +1,383 @@
+use std::{fs, path::Path};
+
+/// Source of a single instruction of the compiled bootloader.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionSource {
+    /// Assembly label of the function that contains the instruction.
+    pub function: Option<String>,
+    /// Line of the compiled Yul file, if the assembly has line info (`.loc` directives).
+    pub line: Option<u32>,
+}
+
+/// Parses the EraVM assembly emitted by zksolc (`--asm`), and returns the source of every
+/// instruction of the code section, indexed by the program counter.
+/// Every instruction line of the assembly is assumed to be a single instruction in the bytecode,
+/// which `tests/debug_info.rs` checks against the compiled test bootloader.
+pub fn parse_assembly(asm: &str) -> Vec<InstructionSource> {
+    let mut instructions = vec![];
+    let mut in_code = true;
+    let mut function = None;
+    let mut line = None;
+
+    for asm_line in asm.lines() {
+        let asm_line = asm_line.split(';').next().unwrap().trim();
+        if asm_line.is_empty() {
+            continue;
+        }
+        if let Some(label) = asm_line.strip_suffix(':') {
+            // Labels starting with a dot are the basic blocks within the function.
+            if !label.starts_with('.') {
+                function = Some(label.to_string());
+                line = None;
+            }
+            continue;
+        }
+        if asm_line.starts_with('.') {
+            let mut parts = asm_line.split_whitespace();
+            match parts.next() {
+                Some(".text") => in_code = true,
+                Some(".data" | ".rodata" | ".bss") => in_code = false,
+                Some(".section") => {
+                    in_code = parts
+                        .next()
+                        .is_some_and(|section| section.starts_with(".text"))
+                }
+                Some(".loc") => {
+                    // .loc <file> <line> [<column>]
+                    line = parts
+                        .nth(1)
+                        .and_then(|line| line.parse().ok())
+                        .filter(|line| *line != 0);
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if in_code {
+            instructions.push(InstructionSource {
+                function: function.clone(),
+                line,
+            });
+        }
+    }
+    instructions
+}
+
+/// Yul function, with the (1-based) lines of its definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YulFunction {
+    pub name: String,
+    pub first_line: u32,
+    pub last_line: u32,
+}
+
+impl YulFunction {
+    pub fn contains(&self, line: u32) -> bool {
+        (self.first_line..=self.last_line).contains(&line)
+    }
+}
+
+/// Returns the functions defined in the Yul source, ordered by their first line.
+/// Nested functions are returned as well.
+pub fn parse_yul_functions(source: &str) -> Vec<YulFunction> {
+    let mut functions = vec![];
+    // Functions whose body is still open, with the brace depth of the body.
+    let mut open: Vec<(String, u32, usize)> = vec![];
+    // Function whose name was seen, but the body hasn't started yet.
+    let mut pending: Option<(String, u32)> = None;
+    let mut depth = 0;
+    let mut in_comment = false;
+
+    for (line_number, line) in (1u32..).zip(source.lines()) {
+        let mut rest = line;
+        while !rest.is_empty() {
+            if in_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        in_comment = false;
+                        rest = &rest[end + 2..];
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            if rest.starts_with("//") {
+                break;
+            }
+            if let Some(after) = rest.strip_prefix("/*") {
+                in_comment = true;
+                rest = after;
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix('"') {
+                // Yul strings can't span multiple lines.
+                rest = after.find('"').map_or("", |end| &after[end + 1..]);
+                continue;
+            }
+
+            let ch = rest.chars().next().unwrap();
+            if ch.is_alphanumeric() || ch == '_' || ch == '$' || ch == '.' {
+                let word_end = rest
+                    .find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '$' || ch == '.'))
+                    .unwrap_or(rest.len());
+                if &rest[..word_end] == "function" {
+                    let after = rest[word_end..].trim_start();
+                    let name_end = after
+                        .find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '$'))
+                        .unwrap_or(after.len());
+                    if name_end > 0 {
+                        pending = Some((after[..name_end].to_string(), line_number));
+                    }
+                }
+                rest = &rest[word_end..];
+                continue;
+            }
+            match ch {
+                '{' => {
+                    depth += 1;
+                    if let Some((name, first_line)) = pending.take() {
+                        open.push((name, first_line, depth));
+                    }
+                }
+                '}' => {
+                    if open.last().is_some_and(|(_, _, body)| *body == depth) {
+                        let (name, first_line, _) = open.pop().unwrap();
+                        functions.push(YulFunction {
+                            name,
+                            first_line,
+                            last_line: line_number,
+                        });
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                _ => {}
+            }
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    functions.sort_by_key(|function| function.first_line);
+    functions
+}
+
+/// Returns the innermost of the (ordered by the first line) functions that contains the line.
+pub fn innermost_function(functions: &[YulFunction], line: u32) -> Option<usize> {
+    functions
+        .iter()
+        .rposition(|function| function.contains(line))
+}
+
+/// Returns the function that the assembly label belongs to.
+pub fn labeled_function(functions: &[YulFunction], label: &str) -> Option<usize> {
+    functions
+        .iter()
+        .position(|function| label_matches(label, &function.name))
+}
+
+// LLVM may add a numeric suffix to the function names (e.g. `getGasPrice_1`).
+fn label_matches(label: &str, name: &str) -> bool {
+    label == name
+        || label.strip_prefix(name).is_some_and(|suffix| {
+            suffix.len() > 1
+                && suffix.starts_with(['_', '.'])
+                && suffix[1..].chars().all(|ch| ch.is_ascii_digit())
+        })
+}
+
+/// Yul functions of the compiled bootloader that the instructions belong to.
+pub struct FunctionMap {
+    functions: Vec<YulFunction>,
+    /// Index of the function for every instruction, indexed by the program counter.
+    instruction_functions: Vec<Option<usize>>,
+}
+
+impl FunctionMap {
+    /// Instructions are attributed to the function containing their line. Instructions without line info
+    /// belong to the same function as the preceding instruction of their assembly label, as the functions
+    /// inlined by the optimizer share the label of their caller. Only at the start of the label (before any
+    /// line info) the function of the label itself is used.
+    pub fn new(instructions: &[InstructionSource], functions: Vec<YulFunction>) -> Self {
+        let mut label = None;
+        let mut previous = None;
+        let instruction_functions = instructions
+            .iter()
+            .map(|instruction| {
+                if label != instruction.function.as_ref() {
+                    label = instruction.function.as_ref();
+                    previous = label.and_then(|label| labeled_function(&functions, label));
+                }
+                if let Some(line) = instruction.line {
+                    previous = innermost_function(&functions, line);
+                }
+                previous
+            })
+            .collect();
+        Self {
+            functions,
+            instruction_functions,
+        }
+    }
+
+    /// Loads the assembly of the compiled bootloader, and the (preprocessed) Yul source it was compiled from.
+    pub fn load(asm_path: &Path, source_path: &Path) -> Result<Self, String> {
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))
+        };
+        Ok(Self::new(
+            &parse_assembly(&read(asm_path)?),
+            parse_yul_functions(&read(source_path)?),
+        ))
+    }
+
+    /// Returns the index of the function that the instruction belongs to.
+    pub fn function_at(&self, pc: u16) -> Option<usize> {
+        self.instruction_functions
+            .get(pc as usize)
+            .copied()
+            .flatten()
+    }
+
+    pub fn name(&self, function: usize) -> &str {
+        &self.functions[function].name
+    }
+}
+
+/// Loads the map from the lines of the preprocessed bootloader to the lines of `bootloader.yul`,
+/// written by `preprocess-bootloader.ts`. Lines that are not from `bootloader.yul` are `None`.
+pub fn load_line_map(path: &Path) -> Result<Vec<Option<u32>>, String> {
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    serde_json::from_str(&data).map_err(|err| format!("Failed to parse {:?}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assembly() {
+        let asm = r#"
+	.text
+	.file	"bootloader_test.yul"
+	.globl	__entry
+__entry:
+.func_begin0:
+	.loc	1 12 5
+	add	128, r0, r3
+	st.1	64, r3 ; free memory pointer
+.BB0_1:
+	.loc	1 0 0
+	near_call	r0, @getGasPrice, @DEFAULT_UNWIND
+getGasPrice:
+	.loc	1 40 9
+	ret
+	.rodata
+CPI0_0:
+	.cell 42
+"#;
+        let instruction = |function: &str, line| InstructionSource {
+            function: Some(function.to_string()),
+            line,
+        };
+        assert_eq!(
+            parse_assembly(asm),
+            vec![
+                instruction("__entry", Some(12)),
+                instruction("__entry", Some(12)),
+                instruction("__entry", None),
+                instruction("getGasPrice", Some(40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_map_inlined() {
+        let functions = vec![
+            YulFunction {
+                name: "outer".to_string(),
+                first_line: 1,
+                last_line: 5,
+            },
+            YulFunction {
+                name: "inlined".to_string(),
+                first_line: 7,
+                last_line: 9,
+            },
+        ];
+        let instruction = |line| InstructionSource {
+            function: Some("outer".to_string()),
+            line,
+        };
+        // `inlined` was inlined into `outer`, and some of its instructions lost the line info.
+        let map = FunctionMap::new(
+            &[
+                instruction(None),
+                instruction(Some(2)),
+                instruction(Some(8)),
+                instruction(None),
+                instruction(Some(3)),
+            ],
+            functions,
+        );
+        let names: Vec<_> = (0..6)
+            .map(|pc| map.function_at(pc).map(|function| map.name(function)))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                Some("outer"),
+                Some("outer"),
+                Some("inlined"),
+                Some("inlined"),
+                Some("outer"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_label_matches() {
+        assert!(label_matches("getGasPrice", "getGasPrice"));
+        assert!(label_matches("getGasPrice_12", "getGasPrice"));
+        assert!(!label_matches("getGasPriceForTx", "getGasPrice"));
+        assert!(!label_matches("getGasPrice_", "getGasPrice"));
+    }
+
+    #[test]
+    fn test_parse_yul_functions() {
+        let source = r#"object "Bootloader" {
+    code {
+        // function commented(x) {
+        function getGasPrice(x) -> ret {
+            ret := x /* } */
+            let s := "function inString() {"
+        }
+
+        function outer() {
+            function inner() {
+                if 1 { }
+            }
+        }
+    }
+}"#;
+        assert_eq!(
+            parse_yul_functions(source),
+            vec![
+                YulFunction {
+                    name: "getGasPrice".to_string(),
+                    first_line: 4,
+                    last_line: 7
+                },
+                YulFunction {
+                    name: "outer".to_string(),
+                    first_line: 9,
+                    last_line: 13
+                },
+                YulFunction {
+                    name: "inner".to_string(),
+                    first_line: 10,
+                    last_line: 12
+                },
+            ]
+        );
+    }
+}
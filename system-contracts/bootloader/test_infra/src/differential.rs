@@ -0,0 +1,111 @@
+use std::mem;
+
+use zksync_multivm::interface::{ExecutionResult, VmExecutionResultAndLogs};
+
+use crate::output_snapshot::OutputSnapshot;
+
+/// Compares the runs of a test on the legacy VM (`vm_latest`) and on the fast VM: the execution result, the ergs used
+/// and the emitted logs, events and storage diffs. Returns the description of each divergence.
+///
+/// The testing hooks are only handled on the legacy VM, so the asserts of the test are only checked there.
+/// Halts are compared by their reason variant, as the VMs may word the same failure differently (e.g. the message of
+/// `UnexpectedVMBehavior`). Cycles are not compared, as the fast VM doesn't count them the same way.
+pub fn divergences(
+    legacy: &VmExecutionResultAndLogs,
+    fast: &VmExecutionResultAndLogs,
+) -> Vec<String> {
+    let mut divergences = vec![];
+    divergences.extend(result_divergence(&legacy.result, &fast.result));
+    if legacy.statistics.gas_used != fast.statistics.gas_used {
+        divergences.push(format!(
+            "{} ergs used on the legacy VM, {} on the fast VM",
+            legacy.statistics.gas_used, fast.statistics.gas_used
+        ));
+    }
+    divergences.extend(
+        OutputSnapshot::new(&fast.logs)
+            .diff(&OutputSnapshot::new(&legacy.logs))
+            .into_iter()
+            .map(|difference| format!("outputs on the fast VM: {}", difference)),
+    );
+    divergences
+}
+
+fn result_kind(result: &ExecutionResult) -> &'static str {
+    match result {
+        ExecutionResult::Success { .. } => "success",
+        ExecutionResult::Revert { .. } => "revert",
+        ExecutionResult::Halt { .. } => "halt",
+    }
+}
+
+// Compares the outputs of successes and reverts, and the variants of the halt reasons, see `divergences`.
+fn result_divergence(legacy: &ExecutionResult, fast: &ExecutionResult) -> Option<String> {
+    let same = match (legacy, fast) {
+        (
+            ExecutionResult::Success { output: legacy },
+            ExecutionResult::Success { output: fast },
+        ) => legacy == fast,
+        (ExecutionResult::Revert { output: legacy }, ExecutionResult::Revert { output: fast }) => {
+            legacy == fast
+        }
+        (ExecutionResult::Halt { reason: legacy }, ExecutionResult::Halt { reason: fast }) => {
+            mem::discriminant(legacy) == mem::discriminant(fast)
+        }
+        _ => false,
+    };
+    (!same).then(|| {
+        format!(
+            "{} on the legacy VM ({:?}), {} on the fast VM ({:?})",
+            result_kind(legacy),
+            legacy,
+            result_kind(fast),
+            fast
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use zksync_multivm::interface::{Halt, VmRevertReason};
+
+    use super::*;
+
+    #[test]
+    fn test_result_divergence() {
+        let halt = |reason: &str| ExecutionResult::Halt {
+            reason: Halt::UnexpectedVMBehavior(reason.to_string()),
+        };
+        let revert = |msg: &str| ExecutionResult::Revert {
+            output: VmRevertReason::General {
+                msg: msg.to_string(),
+                data: vec![],
+            },
+        };
+        let success = ExecutionResult::Success { output: vec![] };
+
+        assert_eq!(result_divergence(&success, &success), None);
+        assert_eq!(result_divergence(&revert("x"), &revert("x")), None);
+        // Only the message of the halt differs.
+        assert_eq!(
+            result_divergence(&halt("Assertion error: x"), &halt("other")),
+            None
+        );
+
+        let out_of_gas = ExecutionResult::Halt {
+            reason: Halt::BootloaderOutOfGas,
+        };
+        assert!(result_divergence(&halt("Assertion error: x"), &out_of_gas)
+            .unwrap()
+            .ends_with("halt on the fast VM (Halt { reason: BootloaderOutOfGas })"));
+        assert!(result_divergence(&revert("x"), &revert("y"))
+            .unwrap()
+            .starts_with("revert on the legacy VM"));
+        assert!(
+            result_divergence(&success, &ExecutionResult::Success { output: vec![1] }).is_some()
+        );
+        assert!(result_divergence(&success, &halt("x"))
+            .unwrap()
+            .starts_with("success on the legacy VM (Success { output: [] }), halt on the fast VM"));
+    }
+}
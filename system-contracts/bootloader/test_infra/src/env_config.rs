@@ -0,0 +1,287 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use zksync_multivm::interface::{L1BatchEnv, SystemEnv, TxExecutionMode};
+use zksync_types::{
+    fee_model::{BatchFeeInput, L1PeggedBatchFeeModelInput, PubdataIndependentBatchFeeModelInput},
+    L1BatchNumber, L2ChainId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ExecutionMode {
+    VerifyExecute,
+    EstimateFee,
+    EthCall,
+}
+
+impl From<ExecutionMode> for TxExecutionMode {
+    fn from(mode: ExecutionMode) -> Self {
+        match mode {
+            ExecutionMode::VerifyExecute => TxExecutionMode::VerifyExecute,
+            ExecutionMode::EstimateFee => TxExecutionMode::EstimateFee,
+            ExecutionMode::EthCall => TxExecutionMode::EthCall,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FeeInputConfig {
+    L1Pegged {
+        l1_gas_price: u64,
+        fair_l2_gas_price: u64,
+    },
+    PubdataIndependent {
+        l1_gas_price: u64,
+        fair_l2_gas_price: u64,
+        fair_pubdata_price: u64,
+    },
+}
+
+impl From<FeeInputConfig> for BatchFeeInput {
+    fn from(config: FeeInputConfig) -> Self {
+        match config {
+            FeeInputConfig::L1Pegged {
+                l1_gas_price,
+                fair_l2_gas_price,
+            } => BatchFeeInput::L1Pegged(L1PeggedBatchFeeModelInput {
+                fair_l2_gas_price,
+                l1_gas_price,
+            }),
+            FeeInputConfig::PubdataIndependent {
+                l1_gas_price,
+                fair_l2_gas_price,
+                fair_pubdata_price,
+            } => BatchFeeInput::PubdataIndependent(PubdataIndependentBatchFeeModelInput {
+                fair_l2_gas_price,
+                fair_pubdata_price,
+                l1_gas_price,
+            }),
+        }
+    }
+}
+
+/// Overrides of the environment that the bootloader tests are executed in.
+/// Unset values keep the defaults of the runner.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvConfig {
+    pub bootloader_gas_limit: Option<u32>,
+    pub execution_mode: Option<ExecutionMode>,
+    pub chain_id: Option<u32>,
+    pub batch_number: Option<u32>,
+    /// Timestamp of the batch. The first L2 block of the batch gets the next timestamp.
+    pub batch_timestamp: Option<u64>,
+    pub fee_input: Option<FeeInputConfig>,
+    pub enforced_base_fee: Option<u64>,
+}
+
+impl EnvConfig {
+    /// Returns the config with the values set in `other` taking precedence.
+    pub fn overridden_by(&self, other: &EnvConfig) -> EnvConfig {
+        EnvConfig {
+            bootloader_gas_limit: other.bootloader_gas_limit.or(self.bootloader_gas_limit),
+            execution_mode: other.execution_mode.or(self.execution_mode),
+            chain_id: other.chain_id.or(self.chain_id),
+            batch_number: other.batch_number.or(self.batch_number),
+            batch_timestamp: other.batch_timestamp.or(self.batch_timestamp),
+            fee_input: other.fee_input.or(self.fee_input),
+            enforced_base_fee: other.enforced_base_fee.or(self.enforced_base_fee),
+        }
+    }
+
+    pub fn apply(&self, system_env: &mut SystemEnv, l1_batch_env: &mut L1BatchEnv) {
+        if let Some(bootloader_gas_limit) = self.bootloader_gas_limit {
+            system_env.bootloader_gas_limit = bootloader_gas_limit;
+        }
+        if let Some(execution_mode) = self.execution_mode {
+            system_env.execution_mode = execution_mode.into();
+        }
+        if let Some(chain_id) = self.chain_id {
+            system_env.chain_id = L2ChainId::from(chain_id);
+        }
+        if let Some(batch_number) = self.batch_number {
+            l1_batch_env.number = L1BatchNumber(batch_number);
+        }
+        if let Some(batch_timestamp) = self.batch_timestamp {
+            l1_batch_env.timestamp = batch_timestamp;
+            l1_batch_env.first_l2_block.timestamp = batch_timestamp + 1;
+        }
+        if let Some(fee_input) = self.fee_input {
+            l1_batch_env.fee_input = fee_input.into();
+        }
+        if let Some(enforced_base_fee) = self.enforced_base_fee {
+            l1_batch_env.enforced_base_fee = Some(enforced_base_fee);
+        }
+    }
+}
+
+/// Config file with the environment of the test run (`[default]`), and the named environments
+/// that the tests can request via `testing_useEnv` (`[envs.<name>]`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvConfigFile {
+    pub default: EnvConfig,
+    pub envs: BTreeMap<String, EnvConfig>,
+}
+
+/// Named environments, that are applied on top of the environment of the test run.
+pub type Envs = BTreeMap<String, EnvConfig>;
+
+/// Config file that is used if no `--env-config` is passed.
+pub fn default_env_config_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/env_config.toml")
+}
+
+fn parse_env_config(path: &Path, data: &str) -> Result<EnvConfigFile, String> {
+    let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(data).map_err(|err| err.to_string()),
+        _ => toml::from_str(data).map_err(|err| err.to_string()),
+    };
+    parsed.map_err(|err| format!("Failed to parse env config {:?}: {}", path, err))
+}
+
+/// Loads the TOML (or JSON) env config. Returns an empty config if the file doesn't exist.
+pub fn load_env_config(path: &Path) -> Result<EnvConfigFile, String> {
+    if !path.exists() {
+        return Ok(EnvConfigFile::default());
+    }
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    parse_env_config(path, &data)
+}
+
+/// Command line overrides of the environment, applied on top of the `[default]` section of the config file.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct EnvArgs {
+    /// TOML (or JSON) file with the environment of the test run and the named environments for `testing_useEnv`.
+    /// Defaults to `src/env_config.toml`.
+    #[arg(long)]
+    pub env_config: Option<PathBuf>,
+    #[arg(long)]
+    pub bootloader_gas_limit: Option<u32>,
+    #[arg(long, value_enum)]
+    pub execution_mode: Option<ExecutionMode>,
+    #[arg(long)]
+    pub chain_id: Option<u32>,
+    #[arg(long)]
+    pub batch_number: Option<u32>,
+    #[arg(long)]
+    pub batch_timestamp: Option<u64>,
+    /// L1 gas price of the batch fee input. Requires `--fair-l2-gas-price`.
+    #[arg(long, requires = "fair_l2_gas_price")]
+    pub l1_gas_price: Option<u64>,
+    /// Fair L2 gas price of the batch fee input. Requires `--l1-gas-price`.
+    #[arg(long, requires = "l1_gas_price")]
+    pub fair_l2_gas_price: Option<u64>,
+    /// Switches the batch fee input to the pubdata independent model, with this fair pubdata price.
+    #[arg(long, requires = "l1_gas_price")]
+    pub fair_pubdata_price: Option<u64>,
+    #[arg(long)]
+    pub enforced_base_fee: Option<u64>,
+}
+
+impl EnvArgs {
+    pub fn to_config(&self) -> EnvConfig {
+        let fee_input = match (
+            self.l1_gas_price,
+            self.fair_l2_gas_price,
+            self.fair_pubdata_price,
+        ) {
+            (Some(l1_gas_price), Some(fair_l2_gas_price), None) => Some(FeeInputConfig::L1Pegged {
+                l1_gas_price,
+                fair_l2_gas_price,
+            }),
+            (Some(l1_gas_price), Some(fair_l2_gas_price), Some(fair_pubdata_price)) => {
+                Some(FeeInputConfig::PubdataIndependent {
+                    l1_gas_price,
+                    fair_l2_gas_price,
+                    fair_pubdata_price,
+                })
+            }
+            // Other combinations are rejected by clap.
+            _ => None,
+        };
+        EnvConfig {
+            bootloader_gas_limit: self.bootloader_gas_limit,
+            execution_mode: self.execution_mode,
+            chain_id: self.chain_id,
+            batch_number: self.batch_number,
+            batch_timestamp: self.batch_timestamp,
+            fee_input,
+            enforced_base_fee: self.enforced_base_fee,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_config() {
+        let toml = r#"
+            [default]
+            chain_id = 299
+
+            [envs.eth_call]
+            execution_mode = "eth_call"
+            enforced_base_fee = 1000000000
+
+            [envs.pubdata_independent_fee]
+            fee_input = { model = "pubdata_independent", l1_gas_price = 1, fair_l2_gas_price = 2, fair_pubdata_price = 3 }
+        "#;
+        let config = parse_env_config(Path::new("env.toml"), toml).unwrap();
+        assert_eq!(config.default.chain_id, Some(299));
+        assert_eq!(
+            config.envs["eth_call"].execution_mode,
+            Some(ExecutionMode::EthCall)
+        );
+        assert_eq!(
+            config.envs["pubdata_independent_fee"].fee_input,
+            Some(FeeInputConfig::PubdataIndependent {
+                l1_gas_price: 1,
+                fair_l2_gas_price: 2,
+                fair_pubdata_price: 3
+            })
+        );
+
+        let err = parse_env_config(Path::new("env.toml"), "[default]\nchain = 1").unwrap_err();
+        assert!(err.contains("unknown field"), "{}", err);
+    }
+
+    #[test]
+    fn test_overridden_by() {
+        let base = EnvConfig {
+            chain_id: Some(299),
+            batch_number: Some(1),
+            ..EnvConfig::default()
+        };
+        let overrides = EnvConfig {
+            batch_number: Some(5),
+            enforced_base_fee: Some(100),
+            ..EnvConfig::default()
+        };
+        assert_eq!(
+            base.overridden_by(&overrides),
+            EnvConfig {
+                chain_id: Some(299),
+                batch_number: Some(5),
+                enforced_base_fee: Some(100),
+                ..EnvConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_default_env_config() {
+        let config = load_env_config(&default_env_config_path()).unwrap();
+        assert!(config.envs.contains_key("enforced_base_fee"));
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+
+use regex::Regex;
+use zksync_multivm::interface::{ExecutionResult, Halt, VmRevertReason};
+
+/// Failure that the test expects, requested via `testing_testWillFailWith` and the related hooks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectedFailure {
+    /// Assertion error (`assertionError` in the bootloader) with exactly this message.
+    Assert(String),
+    /// Assertion error with the message starting with this prefix.
+    AssertPrefix(String),
+    /// Assertion error with the message matching this regex.
+    AssertRegex(String),
+    /// Revert (or a halt with revert data, e.g. `PayForTxFailed`) whose data starts with this selector.
+    RevertSelector([u8; 4]),
+    /// Revert (or a halt with revert data) with exactly this data, e.g. an ABI-encoded custom error.
+    RevertData(Vec<u8>),
+    /// Halt of this variant, e.g. `BootloaderOutOfGas`.
+    Halt(String),
+    /// Timeout on the `cycles` or `ergs` limit of the test run (see `testing_setLimits`).
+    Timeout(String),
+}
+
+impl fmt::Display for ExpectedFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Assert(message) => write!(f, "{}", message),
+            Self::AssertPrefix(prefix) => write!(f, "an assert starting with `{}`", prefix),
+            Self::AssertRegex(regex) => write!(f, "an assert matching `{}`", regex),
+            Self::RevertSelector(selector) => {
+                write!(f, "a revert with selector 0x{}", hex::encode(selector))
+            }
+            Self::RevertData(data) => write!(f, "a revert with data 0x{}", hex::encode(data)),
+            Self::Halt(variant) => write!(f, "halt `{}`", variant),
+            Self::Timeout(limit) => write!(f, "a timeout on {}", limit),
+        }
+    }
+}
+
+impl ExpectedFailure {
+    /// Checks that the test failed as expected.
+    pub fn check(&self, result: &ExecutionResult) -> Result<(), String> {
+        let matches = match self {
+            Self::Assert(message) => assert_message(result) == Some(message.as_str()),
+            Self::AssertPrefix(prefix) => {
+                assert_message(result).is_some_and(|message| message.starts_with(prefix))
+            }
+            Self::AssertRegex(regex) => {
+                let regex = Regex::new(regex).map_err(|err| {
+                    format!("Invalid regex `{}` of the expected failure: {}", regex, err)
+                })?;
+                assert_message(result).is_some_and(|message| regex.is_match(message))
+            }
+            Self::RevertSelector(selector) => {
+                revert_data(result).is_some_and(|data| data.starts_with(selector))
+            }
+            Self::RevertData(expected) => revert_data(result).as_ref() == Some(expected),
+            Self::Halt(variant) => match result {
+                ExecutionResult::Halt { reason } => halt_variant(reason) == *variant,
+                _ => false,
+            },
+            // The test wasn't stopped by the limits, otherwise the timeout is checked instead.
+            Self::Timeout(_) => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(format!(
+                "Should have failed with {}, but {}",
+                self,
+                describe_result(result)
+            ))
+        }
+    }
+
+    /// Checks that the test run timed out as expected. `exceeded` describes the exceeded limit, as returned by
+    /// [`TestLimits::exceeded`](crate::limits::TestLimits::exceeded).
+    pub fn check_timeout(&self, exceeded: &str) -> Result<(), String> {
+        match self {
+            Self::Timeout(limit) if exceeded.ends_with(&format!(" {}", limit)) => Ok(()),
+            _ => Err(format!(
+                "Should have failed with {}, but timed out: {}",
+                self, exceeded
+            )),
+        }
+    }
+}
+
+// Returns the message of the assertion error that the run failed with.
+fn assert_message(result: &ExecutionResult) -> Option<&str> {
+    match result {
+        ExecutionResult::Halt {
+            reason: Halt::UnexpectedVMBehavior(reason),
+        } => Some(reason.strip_prefix("Assertion error: ").unwrap_or(reason)),
+        _ => None,
+    }
+}
+
+// Returns the data that the run reverted with, including the data of the halts caused by a revert.
+fn revert_data(result: &ExecutionResult) -> Option<Vec<u8>> {
+    let reason = match result {
+        ExecutionResult::Revert { output } => output,
+        ExecutionResult::Halt { reason } => match reason {
+            Halt::ValidationFailed(reason)
+            | Halt::PaymasterValidationFailed(reason)
+            | Halt::PrePaymasterPreparationFailed(reason)
+            | Halt::PayForTxFailed(reason)
+            | Halt::FailedToMarkFactoryDependencies(reason)
+            | Halt::FailedToChargeFee(reason)
+            | Halt::Unknown(reason) => reason,
+            _ => return None,
+        },
+        ExecutionResult::Success { .. } => return None,
+    };
+    match reason {
+        VmRevertReason::General { data, .. } => Some(data.clone()),
+        // Unknown errors are split into the selector and the rest of the data.
+        VmRevertReason::Unknown {
+            function_selector,
+            data,
+        } => Some([function_selector.as_slice(), data].concat()),
+        _ => Some(vec![]),
+    }
+}
+
+// Name of the variant, e.g. `BootloaderOutOfGas` or `UnexpectedVMBehavior`.
+fn halt_variant(halt: &Halt) -> String {
+    let debug = format!("{:?}", halt);
+    let end = debug
+        .find(|ch: char| !ch.is_alphanumeric())
+        .unwrap_or(debug.len());
+    debug[..end].to_string()
+}
+
+fn describe_result(result: &ExecutionResult) -> String {
+    match result {
+        ExecutionResult::Success { .. } => "run successfully.".to_string(),
+        ExecutionResult::Revert { output } => format!(
+            "reverted with {} (data 0x{}).",
+            output.to_user_friendly_string(),
+            hex::encode(revert_data(result).unwrap_or_default())
+        ),
+        ExecutionResult::Halt { reason } => match assert_message(result) {
+            Some(message) => format!("failed with assert `{}`.", message),
+            None => format!("halted with `{}` ({}).", halt_variant(reason), reason),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assertion_error(message: &str) -> ExecutionResult {
+        ExecutionResult::Halt {
+            reason: Halt::UnexpectedVMBehavior(format!("Assertion error: {}", message)),
+        }
+    }
+
+    #[test]
+    fn test_assert_matching() {
+        let result = assertion_error("Failed to refund");
+        let check = |expected: ExpectedFailure| expected.check(&result);
+
+        assert!(check(ExpectedFailure::Assert("Failed to refund".into())).is_ok());
+        assert_eq!(
+            check(ExpectedFailure::Assert("Failed".into())).unwrap_err(),
+            "Should have failed with Failed, but failed with assert `Failed to refund`."
+        );
+        assert!(check(ExpectedFailure::AssertPrefix("Failed to".into())).is_ok());
+        assert!(check(ExpectedFailure::AssertPrefix("refund".into())).is_err());
+        assert!(check(ExpectedFailure::AssertRegex(
+            "^Failed to (refund|pay)$".into()
+        ))
+        .is_ok());
+        assert!(check(ExpectedFailure::AssertRegex("^refund".into())).is_err());
+        assert!(check(ExpectedFailure::AssertRegex("(".into()))
+            .unwrap_err()
+            .starts_with("Invalid regex `(`"));
+        assert!(check(ExpectedFailure::Halt("UnexpectedVMBehavior".into())).is_ok());
+    }
+
+    #[test]
+    fn test_revert_matching() {
+        let data = hex::decode("03eb8b54").unwrap();
+        let args = vec![0u8; 64];
+        let result = ExecutionResult::Halt {
+            reason: Halt::PayForTxFailed(VmRevertReason::Unknown {
+                function_selector: data.clone(),
+                data: args.clone(),
+            }),
+        };
+
+        assert!(ExpectedFailure::RevertSelector([0x03, 0xeb, 0x8b, 0x54])
+            .check(&result)
+            .is_ok());
+        assert!(ExpectedFailure::RevertSelector([0x08, 0xc3, 0x79, 0xa0])
+            .check(&result)
+            .is_err());
+        assert!(ExpectedFailure::RevertData([data.clone(), args].concat())
+            .check(&result)
+            .is_ok());
+        assert!(ExpectedFailure::RevertData(data).check(&result).is_err());
+        assert!(ExpectedFailure::Halt("PayForTxFailed".into())
+            .check(&result)
+            .is_ok());
+        assert_eq!(
+            ExpectedFailure::Halt("BootloaderOutOfGas".into())
+                .check(&ExecutionResult::Success { output: vec![] })
+                .unwrap_err(),
+            "Should have failed with halt `BootloaderOutOfGas`, but run successfully."
+        );
+    }
+
+    #[test]
+    fn test_timeout_matching() {
+        let expected = ExpectedFailure::Timeout("ergs".into());
+        assert!(expected.check_timeout("used more than 500 ergs").is_ok());
+        assert_eq!(
+            expected
+                .check_timeout("used more than 1000 cycles")
+                .unwrap_err(),
+            "Should have failed with a timeout on ergs, but timed out: used more than 1000 cycles"
+        );
+        assert!(ExpectedFailure::Assert("Failed".into())
+            .check_timeout("used more than 500 ergs")
+            .is_err());
+        assert_eq!(
+            expected
+                .check(&ExecutionResult::Success { output: vec![] })
+                .unwrap_err(),
+            "Should have failed with a timeout on ergs, but run successfully."
+        );
+    }
+
+    #[test]
+    fn test_halt_variant() {
+        assert_eq!(
+            halt_variant(&Halt::BootloaderOutOfGas),
+            "BootloaderOutOfGas"
+        );
+        assert_eq!(
+            halt_variant(&Halt::FailedToSetL2Block("reason".into())),
+            "FailedToSetL2Block"
+        );
+    }
+}
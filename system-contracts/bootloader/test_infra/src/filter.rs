@@ -0,0 +1,70 @@
+use regex::Regex;
+
+/// Selects which of the discovered bootloader tests should be executed.
+#[derive(Debug, Clone)]
+pub enum TestFilter {
+    /// Run every test.
+    All,
+    /// Run only the test with exactly this name.
+    Exact(String),
+    /// Run tests whose name matches the pattern anywhere.
+    Pattern(Regex),
+}
+
+impl TestFilter {
+    /// Builds the filter from the `--exact` and `--filter` command line options.
+    ///
+    /// The `--filter` value is treated as a regex. If it is not a valid regex, it is
+    /// matched as a plain substring instead.
+    pub fn new(exact: Option<&str>, pattern: Option<&str>) -> Self {
+        match (exact, pattern) {
+            (Some(name), _) => Self::Exact(name.to_string()),
+            (None, Some(pattern)) => Self::Pattern(Regex::new(pattern).unwrap_or_else(|_| {
+                Regex::new(&regex::escape(pattern)).expect("Escaped pattern is a valid regex")
+            })),
+            (None, None) => Self::All,
+        }
+    }
+
+    /// Returns true if no tests are filtered out.
+    pub fn is_all(&self) -> bool {
+        matches!(self, Self::All)
+    }
+
+    pub fn matches(&self, test_name: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Exact(name) => name == test_name,
+            Self::Pattern(pattern) => pattern.is_match(test_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestFilter;
+
+    #[test]
+    fn test_filter() {
+        let all = TestFilter::new(None, None);
+        assert!(all.matches("TEST_safeSub"));
+
+        let exact = TestFilter::new(Some("TEST_safeSub"), None);
+        assert!(exact.matches("TEST_safeSub"));
+        assert!(!exact.matches("TEST_safeSubAssert"));
+
+        let substring = TestFilter::new(None, Some("safeSub"));
+        assert!(substring.matches("TEST_safeSub"));
+        assert!(substring.matches("TEST_safeSubAssert"));
+        assert!(!substring.matches("TEST_safeMul"));
+
+        let regex = TestFilter::new(None, Some("^TEST_validateOperatorProvidedPrices[12]$"));
+        assert!(regex.matches("TEST_validateOperatorProvidedPrices2"));
+        assert!(!regex.matches("TEST_validateOperatorProvidedPrices3"));
+
+        // Invalid regexes fall back to substring matching.
+        let invalid_regex = TestFilter::new(None, Some("safeSub("));
+        assert!(invalid_regex.matches("TEST_safeSub(1)"));
+        assert!(!invalid_regex.matches("TEST_safeSub"));
+    }
+}
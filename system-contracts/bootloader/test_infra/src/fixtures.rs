@@ -0,0 +1,176 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use zksync_types::Transaction;
+
+use crate::tx_generator;
+
+/// Manifest with the named fixture sets, located in the fixtures directory.
+/// It maps the name of the set to the list of transaction files (relative to the directory),
+/// in the order in which they are pushed into the bootloader. A file is either a single JSON transaction,
+/// or a TOML spec (see [`tx_generator`]), whose transactions are generated when the sets are loaded.
+pub const FIXTURE_MANIFEST: &str = "fixtures.json";
+
+/// Named sets of transactions that the tests can request via `testing_useFixture`.
+pub type FixtureSets = BTreeMap<String, Vec<Transaction>>;
+
+/// Directory with the transactions that are inserted into the bootloader memory by default.
+pub fn default_tx_fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_transactions")
+}
+
+// Checks that the (sorted) fixture indices are 0, 1, 2, ...
+fn check_numbering(indices: &[u32], dir: &Path) -> Result<(), String> {
+    for (expected, &index) in (0u32..).zip(indices) {
+        if index != expected {
+            return Err(format!(
+                "Transaction fixtures in {:?} must be numbered consecutively from 0.json, but {}.json is missing",
+                dir, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the paths of the `<index>.json` files in the directory, ordered by index.
+/// Other files (e.g. README.md) are ignored.
+pub fn numbered_fixture_paths(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let read_error = |err: io::Error| {
+        format!(
+            "Failed to read transaction fixtures from {:?}: {}",
+            dir, err
+        )
+    };
+
+    let mut fixtures = vec![];
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let index = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u32>().ok());
+        if let Some(index) = index {
+            fixtures.push((index, path));
+        }
+    }
+    fixtures.sort();
+
+    let indices: Vec<u32> = fixtures.iter().map(|(index, _)| *index).collect();
+    check_numbering(&indices, dir)?;
+    Ok(fixtures.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Loads all the numbered transactions from the directory, in the order in which they should be
+/// pushed into the bootloader (so that `testing_txDataOffset(index)` refers to `<index>.json`).
+pub fn load_transactions(dir: &Path) -> Result<Vec<Transaction>, String> {
+    numbered_fixture_paths(dir)?
+        .iter()
+        .map(|path| load_transaction(path))
+        .collect()
+}
+
+fn load_transaction(path: &Path) -> Result<Transaction, String> {
+    let json_str =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    serde_json::from_str(&json_str)
+        .map_err(|err| format!("Failed to parse transaction {:?}: {}", path, err))
+}
+
+// Loads the transactions of a file listed in the manifest.
+fn load_fixture_file(path: &Path) -> Result<Vec<Transaction>, String> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("toml") {
+        let spec = tx_generator::load_tx_spec(path)?;
+        tx_generator::generate_transactions(&spec).map_err(|err| format!("{:?}: {}", path, err))
+    } else {
+        Ok(vec![load_transaction(path)?])
+    }
+}
+
+/// Loads the named fixture sets from the manifest in the directory.
+/// Returns no sets if there is no manifest.
+pub fn load_fixture_sets(dir: &Path) -> Result<FixtureSets, String> {
+    let manifest_path = dir.join(FIXTURE_MANIFEST);
+    if !manifest_path.exists() {
+        return Ok(FixtureSets::new());
+    }
+    let manifest = fs::read_to_string(&manifest_path)
+        .map_err(|err| format!("Failed to read {:?}: {}", manifest_path, err))?;
+    let manifest: BTreeMap<String, Vec<PathBuf>> = serde_json::from_str(&manifest)
+        .map_err(|err| format!("Failed to parse {:?}: {}", manifest_path, err))?;
+
+    manifest
+        .into_iter()
+        .map(|(name, files)| {
+            let mut transactions = vec![];
+            for file in &files {
+                transactions.extend(load_fixture_file(&dir.join(file))?);
+            }
+            Ok((name, transactions))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use zksync_types::l2::TransactionType;
+
+    use super::*;
+
+    #[test]
+    fn test_check_numbering() {
+        let dir = Path::new("test_transactions");
+        assert!(check_numbering(&[], dir).is_ok());
+        assert!(check_numbering(&[0, 1, 2], dir).is_ok());
+
+        let err = check_numbering(&[0, 2, 3], dir).unwrap_err();
+        assert!(err.contains("1.json is missing"), "{}", err);
+        let err = check_numbering(&[1], dir).unwrap_err();
+        assert!(err.contains("0.json is missing"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_default_fixtures() {
+        let transactions = load_transactions(&default_tx_fixtures_dir()).unwrap();
+        assert!(!transactions.is_empty());
+
+        let fixture_sets = load_fixture_sets(&default_tx_fixtures_dir()).unwrap();
+        assert!(fixture_sets
+            .values()
+            .all(|transactions| !transactions.is_empty()));
+
+        // The sets used by `bootloader_test.yul`.
+        let tx_type = |set: &str| fixture_sets[set][0].tx_format();
+        assert_eq!(
+            tx_type("eip712_transfer"),
+            TransactionType::EIP712Transaction
+        );
+        assert_eq!(
+            tx_type("l1_priority"),
+            TransactionType::PriorityOpTransaction
+        );
+        assert_eq!(
+            tx_type("upgrade"),
+            TransactionType::ProtocolUpgradeTransaction
+        );
+        assert_eq!(tx_type("paymaster"), TransactionType::EIP712Transaction);
+        let signed_requests: Vec<_> = fixture_sets["signed_requests"]
+            .iter()
+            .map(Transaction::tx_format)
+            .collect();
+        assert_eq!(
+            signed_requests,
+            [
+                TransactionType::LegacyTransaction,
+                TransactionType::EIP1559Transaction
+            ]
+        );
+    }
+}
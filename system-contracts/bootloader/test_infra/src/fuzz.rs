@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use zksync_types::U256;
+
+use crate::hook::test_hook_as_int_or_hex;
+
+/// Default number of runs of each fuzz test.
+pub const DEFAULT_RUNS: u32 = 256;
+/// Default maximum number of runs spent on shrinking the inputs of a failed run.
+pub const DEFAULT_MAX_SHRINK_RUNS: u32 = 512;
+
+/// Number of runs of each fuzz test, and the seed of the first run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FuzzConfig {
+    pub runs: u32,
+    pub seed: u64,
+    /// Maximum number of runs spent on shrinking the inputs of a failed run.
+    pub max_shrink_runs: u32,
+}
+
+impl FuzzConfig {
+    /// Seed of the given run. The first run uses the configured seed, so that a failed run can be
+    /// reproduced with `--fuzz-seed <seed> --fuzz-runs 1`.
+    pub fn run_seed(&self, run: u32) -> u64 {
+        self.seed.wrapping_add(run as u64)
+    }
+}
+
+/// Words that the test requested via `testing_fuzzWord`, keyed by their index.
+pub type FuzzWords = BTreeMap<u32, U256>;
+
+/// Inputs of a single run of a fuzz test.
+/// Every word is derived from the seed and its index, unless it is overridden (while shrinking).
+#[derive(Debug, Clone, Default)]
+pub struct FuzzInputs {
+    seed: u64,
+    overrides: FuzzWords,
+    used: FuzzWords,
+}
+
+impl FuzzInputs {
+    pub fn new(seed: u64) -> Self {
+        Self::with_overrides(seed, FuzzWords::new())
+    }
+
+    pub fn with_overrides(seed: u64, overrides: FuzzWords) -> Self {
+        Self {
+            seed,
+            overrides,
+            used: FuzzWords::new(),
+        }
+    }
+
+    /// Returns the word with the given index, and records it as used by the run.
+    pub fn word(&mut self, index: u32) -> U256 {
+        let word = match self.overrides.get(&index) {
+            Some(word) => *word,
+            None => {
+                let stream = (index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                generate_word(&mut SplitMix64(self.seed ^ stream))
+            }
+        };
+        self.used.insert(index, word);
+        word
+    }
+
+    /// Words requested by the run. Empty if the test is not a fuzz test.
+    pub fn into_used(self) -> FuzzWords {
+        self.used
+    }
+}
+
+// Small and fast PRNG (https://prng.di.unimi.it/splitmix64.c), good enough for generating the inputs.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_word(&mut self) -> U256 {
+        U256([self.next(), self.next(), self.next(), self.next()])
+    }
+}
+
+// Uniformly random words are almost always huge, so the bugs around zero, small values and the
+// overflow boundaries would rarely be hit. A part of the words is taken from these instead.
+fn generate_word(rng: &mut SplitMix64) -> U256 {
+    let bits = (rng.next() % 256) as usize + 1;
+    match rng.next() % 8 {
+        // Edge values: 0, 1, 2, MAX, MAX - 1 and the powers of two (minus one).
+        0 => match rng.next() % 5 {
+            0 => U256::from(rng.next() % 3),
+            1 => U256::MAX - U256::from(rng.next() % 2),
+            2 => U256::one() << (bits - 1),
+            3 => (U256::one() << (bits - 1)) - 1,
+            _ => U256::MAX >> (256 - bits),
+        },
+        // Random words of random bit length.
+        1..=4 => rng.next_word() >> (256 - bits),
+        _ => rng.next_word(),
+    }
+}
+
+// Smaller values to try instead of the word, from the simplest one.
+fn shrink_candidates(word: U256) -> Vec<U256> {
+    if word.is_zero() {
+        return vec![];
+    }
+    let highest_bit = word.bits() - 1;
+    let mut candidates = vec![
+        U256::zero(),
+        U256::one(),
+        word >> 1,
+        word ^ (U256::one() << highest_bit),
+        word - 1,
+    ];
+    candidates.retain(|candidate| *candidate < word);
+    candidates.dedup();
+    candidates
+}
+
+/// Shrinks the inputs of a failed run: tries to replace each word with a smaller one, as long as the run
+/// still fails, for at most `max_runs` runs. `fails` runs the test with the given words.
+pub fn shrink(
+    mut words: FuzzWords,
+    max_runs: u32,
+    mut fails: impl FnMut(&FuzzWords) -> bool,
+) -> FuzzWords {
+    let mut runs = 0;
+    let mut shrunk = true;
+    while shrunk {
+        shrunk = false;
+        let indices: Vec<u32> = words.keys().copied().collect();
+        for index in indices {
+            for candidate in shrink_candidates(words[&index]) {
+                if runs == max_runs {
+                    return words;
+                }
+                runs += 1;
+                let mut candidate_words = words.clone();
+                candidate_words.insert(index, candidate);
+                if fails(&candidate_words) {
+                    words = candidate_words;
+                    shrunk = true;
+                    break;
+                }
+            }
+        }
+    }
+    words
+}
+
+pub fn format_words(words: &FuzzWords) -> String {
+    let words: Vec<String> = words
+        .iter()
+        .map(|(index, word)| format!("{}: {}", index, test_hook_as_int_or_hex(*word)))
+        .collect();
+    format!("[{}]", words.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_are_reproducible() {
+        let mut inputs = FuzzInputs::new(42);
+        let first = inputs.word(0);
+        let second = inputs.word(1);
+        assert_ne!(first, second);
+        assert_eq!(FuzzInputs::new(42).word(1), second);
+        assert_eq!(
+            inputs.into_used(),
+            FuzzWords::from([(0, first), (1, second)])
+        );
+
+        let mut inputs = FuzzInputs::with_overrides(42, FuzzWords::from([(0, U256::from(7))]));
+        assert_eq!(inputs.word(0), U256::from(7));
+        assert_eq!(inputs.word(1), second);
+    }
+
+    #[test]
+    fn test_generated_words_cover_the_domain() {
+        let mut inputs = FuzzInputs::new(1);
+        let words: Vec<U256> = (0..1000).map(|index| inputs.word(index)).collect();
+        assert!(words.iter().any(|word| word.is_zero()));
+        assert!(words.contains(&U256::MAX));
+        assert!(words.iter().any(|word| word.bits() <= 64));
+        assert!(words.iter().any(|word| word.bits() > 250));
+    }
+
+    #[test]
+    fn test_shrink() {
+        let fails = |words: &FuzzWords| words[&0] >= U256::from(1000) && words[&1].bit(0);
+        let words = FuzzWords::from([(0, U256::MAX - 5), (1, U256::MAX >> 3)]);
+        assert_eq!(
+            shrink(words.clone(), 10_000, fails),
+            FuzzWords::from([(0, U256::from(1000)), (1, U256::one())])
+        );
+        // Without any runs, the words stay as they are.
+        assert_eq!(shrink(words.clone(), 0, fails), words);
+    }
+
+    #[test]
+    fn test_shrink_candidates() {
+        assert!(shrink_candidates(U256::zero()).is_empty());
+        assert_eq!(shrink_candidates(U256::one()), vec![U256::zero()]);
+        assert_eq!(
+            shrink_candidates(U256::from(6)),
+            vec![
+                U256::zero(),
+                U256::one(),
+                U256::from(3),
+                U256::from(2),
+                U256::from(5)
+            ]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::runner::{TestOutcome, TestStats};
+
+/// Per-test costs, keyed by the test name. Serialized with sorted keys, so that the checked-in file
+/// produces readable diffs.
+pub type GasSnapshot = BTreeMap<String, TestStats>;
+
+/// Change of a single metric of a single test, compared to the snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostChange {
+    pub test_name: String,
+    pub metric: &'static str,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl CostChange {
+    /// Relative change, in percent.
+    pub fn percent(&self) -> f64 {
+        if self.old == 0 {
+            100.0
+        } else {
+            (self.new as f64 - self.old as f64) * 100.0 / self.old as f64
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SnapshotComparison {
+    /// Costs that have grown by more than the tolerance.
+    pub regressions: Vec<CostChange>,
+    /// Costs that have decreased.
+    pub improvements: Vec<CostChange>,
+    /// Tests that are not in the snapshot yet.
+    pub new_tests: Vec<String>,
+    /// Tests that are in the snapshot, but no longer exist. Only known if all the tests were executed.
+    pub removed_tests: Vec<String>,
+}
+
+fn metrics(stats: &TestStats) -> [(&'static str, u64); 4] {
+    [
+        ("ergs_used", stats.ergs_used),
+        ("cycles_used", stats.cycles_used.into()),
+        ("pubdata_published", stats.pubdata_published.into()),
+        ("storage_writes", stats.storage_writes as u64),
+    ]
+}
+
+/// Checked-in snapshot of the costs of the bootloader tests.
+pub fn default_snapshot_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots/bootloader_test.json")
+}
+
+pub fn load(path: &Path) -> Result<GasSnapshot, String> {
+    if !path.exists() {
+        return Err(format!(
+            "No snapshot at {:?}, run with --update-snapshot to create it",
+            path
+        ));
+    }
+    let data = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read the snapshot {:?}: {}", path, err))?;
+    serde_json::from_str(&data)
+        .map_err(|err| format!("Failed to parse the snapshot {:?}: {}", path, err))
+}
+
+/// Writes the costs of the executed tests into the snapshot file.
+/// Entries of the tests that were not executed in this run (e.g. filtered out) are preserved.
+pub fn update(
+    path: &Path,
+    outcomes: &[TestOutcome],
+    all_tests_executed: bool,
+) -> Result<(), String> {
+    let mut snapshot = if all_tests_executed || !path.exists() {
+        GasSnapshot::new()
+    } else {
+        load(path)?
+    };
+    for outcome in outcomes {
+        snapshot.insert(outcome.test_name.clone(), outcome.stats);
+    }
+    let write = || -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut data =
+            serde_json::to_string_pretty(&snapshot).expect("Failed to serialize snapshot");
+        data.push('\n');
+        fs::write(path, data)
+    };
+    write().map_err(|err| format!("Failed to write the snapshot {:?}: {}", path, err))
+}
+
+/// Compares the costs of the executed tests with the snapshot.
+/// `tolerance` is the allowed relative increase, in percent. If all the tests were executed, the entries of the
+/// tests that weren't are reported as removed.
+pub fn compare(
+    snapshot: &GasSnapshot,
+    outcomes: &[TestOutcome],
+    tolerance: f64,
+    all_tests_executed: bool,
+) -> SnapshotComparison {
+    let mut comparison = SnapshotComparison::default();
+    for outcome in outcomes {
+        let Some(expected) = snapshot.get(&outcome.test_name) else {
+            comparison.new_tests.push(outcome.test_name.clone());
+            continue;
+        };
+        for ((metric, old), (_, new)) in metrics(expected).into_iter().zip(metrics(&outcome.stats))
+        {
+            let change = CostChange {
+                test_name: outcome.test_name.clone(),
+                metric,
+                old,
+                new,
+            };
+            if new as f64 > old as f64 * (1.0 + tolerance / 100.0) {
+                comparison.regressions.push(change);
+            } else if new < old {
+                comparison.improvements.push(change);
+            }
+        }
+    }
+    if all_tests_executed {
+        comparison.removed_tests = snapshot
+            .keys()
+            .filter(|test_name| {
+                !outcomes
+                    .iter()
+                    .any(|outcome| &outcome.test_name == *test_name)
+            })
+            .cloned()
+            .collect();
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn outcome(test_name: &str, ergs_used: u64, storage_writes: usize) -> TestOutcome {
+        TestOutcome {
+            test_id: 1,
+            test_name: test_name.to_string(),
+            result: Ok(()),
+            requested_assert: None,
+            logs: vec![],
+            stats: TestStats {
+                ergs_used,
+                cycles_used: 1000,
+                pubdata_published: 0,
+                storage_writes,
+            },
+            profile: None,
+            fuzz_runs: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let snapshot: GasSnapshot = [
+            ("TEST_safeSub", outcome("", 10_000, 2).stats),
+            ("TEST_safeMul", outcome("", 10_000, 2).stats),
+            ("TEST_safeDiv", outcome("", 10_000, 2).stats),
+            ("TEST_removed", outcome("", 10_000, 2).stats),
+        ]
+        .into_iter()
+        .map(|(name, stats)| (name.to_string(), stats))
+        .collect();
+
+        let outcomes = vec![
+            // Within the 1% tolerance.
+            outcome("TEST_safeSub", 10_100, 2),
+            // Regression of ergs, improvement of storage writes.
+            outcome("TEST_safeMul", 10_101, 1),
+            outcome("TEST_safeDiv", 10_000, 2),
+            outcome("TEST_safeAdd", 10_000, 2),
+        ];
+        let comparison = compare(&snapshot, &outcomes, 1.0, true);
+
+        assert_eq!(
+            comparison.regressions,
+            vec![CostChange {
+                test_name: "TEST_safeMul".to_string(),
+                metric: "ergs_used",
+                old: 10_000,
+                new: 10_101,
+            }]
+        );
+        assert_eq!(comparison.improvements.len(), 1);
+        assert_eq!(comparison.improvements[0].metric, "storage_writes");
+        assert_eq!(comparison.improvements[0].percent(), -50.0);
+        assert_eq!(comparison.new_tests, vec!["TEST_safeAdd".to_string()]);
+        assert_eq!(comparison.removed_tests, vec!["TEST_removed".to_string()]);
+
+        // With a filter, the tests that weren't executed may still exist.
+        let comparison = compare(&snapshot, &outcomes, 1.0, false);
+        assert!(comparison.removed_tests.is_empty());
+    }
+
+    #[test]
+    fn test_load_missing_snapshot() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots/missing.json");
+        let err = load(&path).unwrap_err();
+        assert!(err.contains("run with --update-snapshot"), "{}", err);
+    }
+}
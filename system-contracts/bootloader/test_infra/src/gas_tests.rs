@@ -0,0 +1,188 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use once_cell::sync::OnceCell;
+use zksync_multivm::tracers::dynamic::vm_1_5_2::DynTracer;
+use zksync_multivm::vm_latest::{HistoryMode, SimpleMemory, VmTracer};
+use zksync_multivm::zk_evm_latest::{
+    tracing::{BeforeExecutionData, VmLocalStateData},
+    zkevm_opcode_defs::{FatPointer, Opcode, RetOpcode},
+};
+use zksync_state::interface::{StoragePtr, WriteStorage};
+use zksync_types::{BOOTLOADER_ADDRESS, U256};
+
+use crate::hook::test_hook_as_bytes;
+
+/// Words that the gas tests are expected to return, keyed by the name of the test.
+pub type GasTestExpectations = BTreeMap<String, Vec<U256>>;
+
+/// Directory with the gas tests: Yul files that replace the whole bootloader (e.g. `transfer_test.yul`).
+pub fn default_gas_tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests")
+}
+
+/// Checked-in file with the words that the gas tests are expected to return.
+pub fn default_expectations_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gas_tests.json")
+}
+
+/// Returns the names of the gas tests (the `.yul` files directly in the directory), sorted.
+pub fn gas_test_names(dir: &Path) -> Result<Vec<String>, String> {
+    let read_error =
+        |err: std::io::Error| format!("Failed to read gas tests from {:?}: {}", dir, err);
+
+    let mut names = vec![];
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("yul") {
+            continue;
+        }
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Loads the expectations. Returns no expectations if the file doesn't exist.
+pub fn load_expectations(path: &Path) -> Result<GasTestExpectations, String> {
+    if !path.exists() {
+        return Ok(GasTestExpectations::new());
+    }
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    serde_json::from_str(&data).map_err(|err| format!("Failed to parse {:?}: {}", path, err))
+}
+
+pub fn write_expectations(path: &Path, expectations: &GasTestExpectations) -> Result<(), String> {
+    let mut data =
+        serde_json::to_string_pretty(expectations).expect("Failed to serialize expectations");
+    data.push('\n');
+    fs::write(path, data).map_err(|err| format!("Failed to write {:?}: {}", path, err))
+}
+
+/// Compares the words returned by the gas test with the expected ones.
+pub fn check_returned(expected: &[U256], returned: &[U256]) -> Result<(), String> {
+    if expected.len() != returned.len() {
+        return Err(format!(
+            "Returned {} words, expected {}",
+            returned.len(),
+            expected.len()
+        ));
+    }
+    let mismatches: Vec<String> = expected
+        .iter()
+        .zip(returned)
+        .enumerate()
+        .filter(|(_, (expected, returned))| expected != returned)
+        .map(|(index, (expected, returned))| {
+            format!("word {}: {:#x} (expected {:#x})", index, returned, expected)
+        })
+        .collect();
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Returned different values: {}",
+            mismatches.join(", ")
+        ))
+    }
+}
+
+// Splits the returned bytes into words, with the last one padded with zeros.
+fn bytes_to_words(bytes: &[u8]) -> Vec<U256> {
+    bytes
+        .chunks(32)
+        .map(|chunk| {
+            let mut word = [0u8; 32];
+            word[..chunk.len()].copy_from_slice(chunk);
+            U256::from_big_endian(&word)
+        })
+        .collect()
+}
+
+/// Tracer that captures the data returned by the bootloader, i.e. by the `return` in its top-level code.
+pub struct ReturnDataTracer {
+    pub returned: Arc<OnceCell<Vec<U256>>>,
+}
+
+impl ReturnDataTracer {
+    pub fn new(returned: Arc<OnceCell<Vec<U256>>>) -> Self {
+        Self { returned }
+    }
+}
+
+impl<S, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for ReturnDataTracer {
+    fn before_execution(
+        &mut self,
+        state: VmLocalStateData<'_>,
+        data: BeforeExecutionData,
+        memory: &SimpleMemory<H>,
+        _storage: StoragePtr<S>,
+    ) {
+        let frame = &state.vm_local_state.callstack.current;
+        if !matches!(data.opcode.variant.opcode, Opcode::Ret(RetOpcode::Ok))
+            || frame.code_address != BOOTLOADER_ADDRESS
+            || frame.is_local_frame
+        {
+            return;
+        }
+        // The bootloader returns a slice of its heap.
+        let fat_ptr = FatPointer::from_u256(data.src0_value.value);
+        let returned = test_hook_as_bytes(memory, fat_ptr.start.into(), fat_ptr.length.into())
+            .map(|bytes| bytes_to_words(&bytes))
+            .unwrap_or_default();
+        self.returned.set(returned).ok();
+    }
+}
+
+impl<S: WriteStorage, H: HistoryMode> VmTracer<S, H> for ReturnDataTracer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_returned() {
+        let expected = vec![U256::from(0x123123123u64), U256::zero()];
+        assert!(check_returned(&expected, &expected).is_ok());
+
+        let err = check_returned(&expected, &[U256::from(5), U256::zero()]).unwrap_err();
+        assert_eq!(
+            err,
+            "Returned different values: word 0: 0x5 (expected 0x123123123)"
+        );
+        let err = check_returned(&expected, &[]).unwrap_err();
+        assert_eq!(err, "Returned 0 words, expected 2");
+    }
+
+    #[test]
+    fn test_bytes_to_words() {
+        let mut bytes = vec![0u8; 31];
+        bytes.push(7);
+        bytes.push(1);
+        assert_eq!(
+            bytes_to_words(&bytes),
+            vec![U256::from(7), U256::from(1) << 248]
+        );
+    }
+
+    #[test]
+    fn test_default_gas_tests() {
+        let names = gas_test_names(&default_gas_tests_dir()).unwrap();
+        assert_eq!(
+            names,
+            vec!["dummy".to_string(), "transfer_test".to_string()]
+        );
+        // Expectations of the removed or renamed tests must be removed as well.
+        let expectations = load_expectations(&default_expectations_path()).unwrap();
+        for name in expectations.keys() {
+            assert!(names.contains(name), "{} is not a gas test", name);
+        }
+    }
+}
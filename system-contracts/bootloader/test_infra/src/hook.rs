@@ -1,26 +1,66 @@
+use std::ops::Range;
+
 use zksync_multivm::vm_latest::{
     constants::{get_vm_hook_start_position_latest, BOOTLOADER_HEAP_PAGE},
     HistoryMode, SimpleMemory,
 };
 
 use zksync_multivm::zk_evm_latest::{
-    aux_structures::MemoryPage,
+    aux_structures::{MemoryPage, Timestamp},
     tracing::{BeforeExecutionData, VmLocalStateData},
     zkevm_opcode_defs::{FatPointer, Opcode, UMAOpcode},
 };
 
-use zksync_types::{U256, u256_to_h256};
+use zksync_types::{u256_to_h256, Address, H256, U256};
+
+use crate::bootloader_hook::BootloaderHook;
+use crate::expected_failure::ExpectedFailure;
+use crate::memory_dump::{self, MemoryRegions};
 
 #[derive(Clone, Debug)]
 pub(crate) enum TestVmHook {
     NoHook,
     TestLog(String, String),
     AssertEqFailed(String, String, String),
-    RequestedAssert(String),
+    // 102, 126 - 130 - failure that the test expects.
+    RequestedAssert(ExpectedFailure),
     // Testing framework reporting the number of tests.
     TestCount(u32),
     // 104 - test start.
     TestStart(String),
+    // 105 - test requesting the named set of transactions to be pushed into the bootloader.
+    UseFixture(String),
+    // 106 - 110 - failed comparisons of two values (a, b, message).
+    AssertNotEqFailed(String, String, String),
+    AssertLtFailed(String, String, String),
+    AssertGtFailed(String, String, String),
+    AssertLeFailed(String, String, String),
+    AssertGeFailed(String, String, String),
+    // 111, 112 - failed boolean checks (value, message).
+    AssertTrueFailed(String, String),
+    AssertFalseFailed(String, String),
+    // 113 - values are further apart than allowed (a, b, max delta, message).
+    AssertApproxEqFailed(String, String, String, String),
+    // 118 - message with several values, formatted according to their type tags.
+    TestLogValues(String, Vec<String>),
+    // 120 - words dumped from the bootloader heap (the offset of the first word, words, known regions).
+    DumpMemory(u32, Vec<U256>, MemoryRegions),
+    // 121 - storage slot that the tracer should check (address, key, expected value, message).
+    AssertStorage(Address, H256, U256, String),
+    // 122 - storage slot that the tracer should log (message, address, key).
+    LogStorage(String, Address, H256),
+    // 123 - test requesting the named initial state to be applied to the storage.
+    UseState(String),
+    // 124 - test requesting the named environment (see env_config.toml) to run in.
+    UseEnv(String),
+    // 125 - fuzz test requesting the word with the given index, which the tracer writes into the first param slot.
+    FuzzWord(u32),
+    // 131 - limits of the test run (max cycles, max ergs), 0 keeps the current limit.
+    SetLimits(u32, u64),
+    // Hook of the bootloader itself (e.g. `debugLog`), triggered at the standard VM hook position.
+    Bootloader(BootloaderHook),
+    // Test hook whose params are invalid (hook name, error), which fails the test.
+    InvalidParams(String, String),
 }
 
 // Number of 32-bytes slots that are reserved for test hooks (passing information between bootloader test code and the VM).
@@ -28,6 +68,10 @@ const TEST_HOOKS: u32 = 5;
 const TEST_HOOK_ENUM_POSITION: u32 = get_vm_hook_start_position_latest() - 1;
 const TEST_HOOK_START: u32 = TEST_HOOK_ENUM_POSITION - TEST_HOOKS;
 
+// Same as `VM_HOOK_PARAMS()` in bootloader.yul. The params of the bootloader hooks are followed by their id (`VM_HOOK_PTR()`).
+const VM_HOOK_PARAMS: u32 = 3;
+const VM_HOOK_POSITION: u32 = get_vm_hook_start_position_latest() + VM_HOOK_PARAMS;
+
 pub fn get_vm_hook_params<H: HistoryMode>(memory: &SimpleMemory<H>) -> Vec<U256> {
     memory.dump_page_content_as_u256_words(
         BOOTLOADER_HEAP_PAGE,
@@ -35,6 +79,19 @@ pub fn get_vm_hook_params<H: HistoryMode>(memory: &SimpleMemory<H>) -> Vec<U256>
     )
 }
 
+// Writes the result of the hook into the first param slot, where the test reads it from after the hook.
+pub(crate) fn set_test_hook_result<H: HistoryMode>(
+    memory: &mut SimpleMemory<H>,
+    timestamp: u32,
+    value: U256,
+) {
+    memory.populate_page(
+        BOOTLOADER_HEAP_PAGE as usize,
+        vec![(TEST_HOOK_START as usize, value)],
+        Timestamp(timestamp),
+    );
+}
+
 fn strip_trailing_zeros(input: &[u8]) -> &[u8] {
     // Find the position of the last non-zero byte.
     let end = input
@@ -53,9 +110,9 @@ fn test_hook_as_string(hook_param: U256) -> String {
     String::from_utf8(strip_trailing_zeros(&msg).to_vec()).expect("Invalid debug message")
 }
 
-fn test_hook_as_int_or_hex(hook_param: U256) -> String {
+pub(crate) fn test_hook_as_int_or_hex(hook_param: U256) -> String {
     // For long data, it is better to use hex-encoding for greater readability
-    if hook_param > U256::from(u64::max_value()) {
+    if hook_param > U256::from(u64::MAX) {
         let mut bytes = [0u8; 32];
         hook_param.to_big_endian(&mut bytes);
         format!("0x{}", hex::encode(bytes))
@@ -64,6 +121,117 @@ fn test_hook_as_int_or_hex(hook_param: U256) -> String {
     }
 }
 
+// Type tags of the values passed to the typed logging hook (see `testing_log3` in test_utils.yul).
+const LOG_TYPE_NONE: u8 = 0;
+const LOG_TYPE_UINT: u8 = 1;
+const LOG_TYPE_INT: u8 = 2;
+const LOG_TYPE_ADDRESS: u8 = 3;
+const LOG_TYPE_BYTES32: u8 = 4;
+const LOG_TYPE_BOOL: u8 = 5;
+const LOG_TYPE_STRING: u8 = 6;
+
+fn format_typed_value(type_tag: u8, value: U256) -> String {
+    match type_tag {
+        LOG_TYPE_UINT => value.to_string(),
+        LOG_TYPE_INT => {
+            if value.bit(255) {
+                // Two's complement negative number.
+                format!("-{}", (!value).overflowing_add(U256::one()).0)
+            } else {
+                value.to_string()
+            }
+        }
+        LOG_TYPE_ADDRESS => format!("0x{}", hex::encode(&u256_to_h256(value)[12..])),
+        LOG_TYPE_BYTES32 => format!("0x{}", hex::encode(u256_to_h256(value))),
+        LOG_TYPE_BOOL => (!value.is_zero()).to_string(),
+        LOG_TYPE_STRING => test_hook_as_string(value),
+        _ => format!(
+            "<unknown type {}: {}>",
+            type_tag,
+            test_hook_as_int_or_hex(value)
+        ),
+    }
+}
+
+// Formats the values of the typed logging hook. The type tag of the i-th value is stored in the i-th
+// lowest byte of `type_tags`, values with no type tag are not logged.
+fn format_typed_values(type_tags: U256, values: &[U256]) -> Vec<String> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (type_tags.byte(i), *value))
+        .filter(|(type_tag, _)| *type_tag != LOG_TYPE_NONE)
+        .map(|(type_tag, value)| format_typed_value(type_tag, value))
+        .collect()
+}
+
+// Longest string or byte array that a test hook can pass via a (pointer, length) pair.
+// Anything longer is most likely a wrong pointer or length.
+pub(crate) const MAX_HOOK_BYTES_LENGTH: usize = 1 << 16;
+
+// Returns `length` bytes starting at `offset` within the first word of `words`.
+fn bytes_from_words(words: &[U256], offset: usize, length: usize) -> Vec<u8> {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| u256_to_h256(*word).to_fixed_bytes())
+        .collect();
+    bytes[offset..offset + length].to_vec()
+}
+
+// Checks the (pointer, length) pair that a test passed via hook params, and returns the range of the heap words
+// that the bytes span.
+fn hook_memory_words(pointer: U256, length: U256) -> Result<Range<u32>, String> {
+    let invalid = || {
+        format!(
+            "<invalid memory slice: pointer {}, length {}>",
+            pointer, length
+        )
+    };
+    if length > U256::from(MAX_HOOK_BYTES_LENGTH) || pointer > U256::from(u32::MAX) {
+        return Err(invalid());
+    }
+    let (pointer, length) = (pointer.as_u32(), length.as_u32());
+    let end = pointer.checked_add(length).ok_or_else(invalid)?;
+    Ok(pointer / 32..end.div_ceil(32))
+}
+
+// Reads the bytes that the test passed via (pointer, length) hook params from the bootloader heap.
+pub(crate) fn test_hook_as_bytes<H: HistoryMode>(
+    memory: &SimpleMemory<H>,
+    pointer: U256,
+    length: U256,
+) -> Result<Vec<u8>, String> {
+    let words = hook_memory_words(pointer, length)?;
+    let words = memory.dump_page_content_as_u256_words(BOOTLOADER_HEAP_PAGE, words);
+    Ok(bytes_from_words(
+        &words,
+        pointer.as_usize() % 32,
+        length.as_usize(),
+    ))
+}
+
+fn test_hook_as_long_string<H: HistoryMode>(
+    memory: &SimpleMemory<H>,
+    pointer: U256,
+    length: U256,
+) -> String {
+    match test_hook_as_bytes(memory, pointer, length) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => err,
+    }
+}
+
+fn test_hook_as_hex_bytes<H: HistoryMode>(
+    memory: &SimpleMemory<H>,
+    pointer: U256,
+    length: U256,
+) -> String {
+    match test_hook_as_bytes(memory, pointer, length) {
+        Ok(bytes) => format!("0x{}", hex::encode(bytes)),
+        Err(err) => err,
+    }
+}
+
 const fn heap_page_from_base(base: MemoryPage) -> MemoryPage {
     MemoryPage(base.0 + 2)
 }
@@ -87,10 +255,23 @@ impl TestVmHook {
         // Only UMA opcodes in the bootloader serve for vm hooks
         if !matches!(opcode_variant.opcode, Opcode::UMA(UMAOpcode::HeapWrite))
             || heap_page != BOOTLOADER_HEAP_PAGE
-            || fat_ptr.offset != TEST_HOOK_ENUM_POSITION * 32
         {
             return Self::NoHook;
         }
+        if fat_ptr.offset == VM_HOOK_POSITION * 32 {
+            let params = memory.dump_page_content_as_u256_words(
+                BOOTLOADER_HEAP_PAGE,
+                get_vm_hook_start_position_latest()..VM_HOOK_POSITION,
+            );
+            return Self::Bootloader(BootloaderHook::from_params(
+                value.low_u32(),
+                &params,
+                memory,
+            ));
+        }
+        if fat_ptr.offset != TEST_HOOK_ENUM_POSITION * 32 {
+            return Self::NoHook;
+        }
         let vm_hook_params: Vec<U256> = get_vm_hook_params(memory);
 
         match value.as_u32() {
@@ -103,20 +284,170 @@ impl TestVmHook {
                 test_hook_as_int_or_hex(vm_hook_params[1]),
                 test_hook_as_string(vm_hook_params[2]),
             ),
-            102 => Self::RequestedAssert(test_hook_as_string(vm_hook_params[0])),
+            102 => Self::RequestedAssert(ExpectedFailure::Assert(test_hook_as_string(
+                vm_hook_params[0],
+            ))),
             103 => Self::TestCount(vm_hook_params[0].as_u32()),
             104 => Self::TestStart(test_hook_as_string(vm_hook_params[0])),
+            105 => Self::UseFixture(test_hook_as_string(vm_hook_params[0])),
+            106..=110 => {
+                let a = test_hook_as_int_or_hex(vm_hook_params[0]);
+                let b = test_hook_as_int_or_hex(vm_hook_params[1]);
+                let msg = test_hook_as_string(vm_hook_params[2]);
+                match value.as_u32() {
+                    106 => Self::AssertNotEqFailed(a, b, msg),
+                    107 => Self::AssertLtFailed(a, b, msg),
+                    108 => Self::AssertGtFailed(a, b, msg),
+                    109 => Self::AssertLeFailed(a, b, msg),
+                    _ => Self::AssertGeFailed(a, b, msg),
+                }
+            }
+            111 => Self::AssertTrueFailed(
+                test_hook_as_int_or_hex(vm_hook_params[0]),
+                test_hook_as_string(vm_hook_params[1]),
+            ),
+            112 => Self::AssertFalseFailed(
+                test_hook_as_int_or_hex(vm_hook_params[0]),
+                test_hook_as_string(vm_hook_params[1]),
+            ),
+            113 => Self::AssertApproxEqFailed(
+                test_hook_as_int_or_hex(vm_hook_params[0]),
+                test_hook_as_int_or_hex(vm_hook_params[1]),
+                test_hook_as_int_or_hex(vm_hook_params[2]),
+                test_hook_as_string(vm_hook_params[3]),
+            ),
+            // 114 - 117 - same as the hooks above, but with the strings (or bytes) passed as
+            // (pointer, length) pairs into the bootloader heap, so that they can be longer than 32 bytes.
+            114 => Self::TestStart(test_hook_as_long_string(
+                memory,
+                vm_hook_params[0],
+                vm_hook_params[1],
+            )),
+            115 => Self::TestLog(
+                test_hook_as_long_string(memory, vm_hook_params[0], vm_hook_params[1]),
+                test_hook_as_int_or_hex(vm_hook_params[2]),
+            ),
+            116 => Self::TestLog(
+                test_hook_as_string(vm_hook_params[0]),
+                test_hook_as_hex_bytes(memory, vm_hook_params[1], vm_hook_params[2]),
+            ),
+            117 => Self::AssertEqFailed(
+                test_hook_as_int_or_hex(vm_hook_params[0]),
+                test_hook_as_int_or_hex(vm_hook_params[1]),
+                test_hook_as_long_string(memory, vm_hook_params[2], vm_hook_params[3]),
+            ),
 
+            118 => Self::TestLogValues(
+                test_hook_as_string(vm_hook_params[0]),
+                format_typed_values(vm_hook_params[1], &vm_hook_params[2..]),
+            ),
+            120 => {
+                let words = match hook_memory_words(vm_hook_params[0], vm_hook_params[1]) {
+                    Ok(words) => words,
+                    Err(err) => return Self::InvalidParams("testing_dumpMemory".to_string(), err),
+                };
+                // The first bytes of the known regions are passed as an array of words.
+                let regions = test_hook_as_bytes(
+                    memory,
+                    vm_hook_params[2],
+                    vm_hook_params[3].saturating_mul(U256::from(32)),
+                )
+                .ok()
+                .and_then(|bytes| {
+                    let words: Vec<U256> = bytes.chunks(32).map(U256::from_big_endian).collect();
+                    memory_dump::memory_regions(&words)
+                })
+                .unwrap_or_default();
+                Self::DumpMemory(
+                    words.start * 32,
+                    memory.dump_page_content_as_u256_words(BOOTLOADER_HEAP_PAGE, words),
+                    regions,
+                )
+            }
+            121 => Self::AssertStorage(
+                Address::from(u256_to_h256(vm_hook_params[0])),
+                u256_to_h256(vm_hook_params[1]),
+                vm_hook_params[2],
+                test_hook_as_string(vm_hook_params[3]),
+            ),
+            122 => Self::LogStorage(
+                test_hook_as_string(vm_hook_params[0]),
+                Address::from(u256_to_h256(vm_hook_params[1])),
+                u256_to_h256(vm_hook_params[2]),
+            ),
+            123 => Self::UseState(test_hook_as_string(vm_hook_params[0])),
+            124 => Self::UseEnv(test_hook_as_string(vm_hook_params[0])),
+            125 => Self::FuzzWord(vm_hook_params[0].low_u32()),
+            126 => Self::RequestedAssert(ExpectedFailure::AssertPrefix(test_hook_as_string(
+                vm_hook_params[0],
+            ))),
+            127 => match test_hook_as_bytes(memory, vm_hook_params[0], vm_hook_params[1]) {
+                Ok(regex) => Self::RequestedAssert(ExpectedFailure::AssertRegex(
+                    String::from_utf8_lossy(&regex).into_owned(),
+                )),
+                Err(err) => Self::TestLog("testing_testWillFailWithRegex".to_string(), err),
+            },
+            128 => {
+                let selector = u256_to_h256(vm_hook_params[0]).to_fixed_bytes();
+                Self::RequestedAssert(ExpectedFailure::RevertSelector(
+                    selector[28..].try_into().unwrap(),
+                ))
+            }
+            129 => match test_hook_as_bytes(memory, vm_hook_params[0], vm_hook_params[1]) {
+                Ok(data) => Self::RequestedAssert(ExpectedFailure::RevertData(data)),
+                Err(err) => Self::TestLog("testing_testWillRevertWithData".to_string(), err),
+            },
+            130 => Self::RequestedAssert(ExpectedFailure::Halt(test_hook_as_string(
+                vm_hook_params[0],
+            ))),
+            131 => Self::SetLimits(
+                vm_hook_params[0].min(U256::from(u32::MAX)).as_u32(),
+                vm_hook_params[1].min(U256::from(u64::MAX)).as_u64(),
+            ),
+            132 => Self::RequestedAssert(ExpectedFailure::Timeout(test_hook_as_string(
+                vm_hook_params[0],
+            ))),
             _ => Self::NoHook,
         }
     }
+
+    /// Returns the failure message, if the hook reports a failed assert.
+    pub(crate) fn assert_failure(&self) -> Option<String> {
+        let failure = match self {
+            Self::AssertEqFailed(a, b, msg) => format!("{} is not equal to {}: {}", a, b, msg),
+            Self::AssertNotEqFailed(a, b, msg) => format!("{} is equal to {}: {}", a, b, msg),
+            Self::AssertLtFailed(a, b, msg) => format!("{} is not less than {}: {}", a, b, msg),
+            Self::AssertGtFailed(a, b, msg) => {
+                format!("{} is not greater than {}: {}", a, b, msg)
+            }
+            Self::AssertLeFailed(a, b, msg) => {
+                format!("{} is not less than or equal to {}: {}", a, b, msg)
+            }
+            Self::AssertGeFailed(a, b, msg) => {
+                format!("{} is not greater than or equal to {}: {}", a, b, msg)
+            }
+            Self::AssertTrueFailed(value, msg) => {
+                format!("expected a non-zero value, got {}: {}", value, msg)
+            }
+            Self::AssertFalseFailed(value, msg) => format!("expected 0, got {}: {}", value, msg),
+            Self::AssertApproxEqFailed(a, b, delta, msg) => {
+                format!("{} is not within {} of {}: {}", a, delta, b, msg)
+            }
+            _ => return None,
+        };
+        Some(format!("Assert failed: {}", failure))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use zksync_types::U256;
 
-    use crate::hook::test_hook_as_string;
+    use crate::hook::{
+        bytes_from_words, format_typed_values, hook_memory_words, test_hook_as_string, TestVmHook,
+        LOG_TYPE_ADDRESS, LOG_TYPE_BOOL, LOG_TYPE_BYTES32, LOG_TYPE_INT, LOG_TYPE_STRING,
+        LOG_TYPE_UINT,
+    };
 
     #[test]
     fn test_to_string() {
@@ -124,4 +455,98 @@ mod tests {
             U256::from("0x77696c6c4661696c000000000000000000000000000000000000000000000000");
         assert_eq!("willFail", test_hook_as_string(data));
     }
+
+    #[test]
+    fn test_bytes_from_words() {
+        let words = [
+            U256::from("0x000000000000000000000000000000000000000000000000000000000000aabb"),
+            U256::from("0xccdd000000000000000000000000000000000000000000000000000000000000"),
+        ];
+        assert_eq!(
+            bytes_from_words(&words, 30, 4),
+            vec![0xaa, 0xbb, 0xcc, 0xdd]
+        );
+        assert_eq!(bytes_from_words(&words, 31, 0), Vec::<u8>::new());
+        assert_eq!(bytes_from_words(&words[1..], 0, 32).len(), 32);
+    }
+
+    #[test]
+    fn test_hook_memory_words() {
+        assert_eq!(hook_memory_words(U256::from(33), U256::from(32)), Ok(1..3));
+        assert_eq!(hook_memory_words(U256::from(64), U256::zero()), Ok(2..2));
+        // The end of the slice doesn't fit into the heap offsets.
+        assert!(hook_memory_words(U256::from(u32::MAX), U256::from(2)).is_err());
+        assert!(hook_memory_words(U256::from(u32::MAX) + 1, U256::zero()).is_err());
+        assert!(hook_memory_words(U256::zero(), U256::from(1_u64 << 17)).is_err());
+    }
+
+    #[test]
+    fn test_format_typed_values() {
+        let type_tags = |tags: &[u8]| {
+            tags.iter()
+                .rev()
+                .fold(U256::zero(), |acc, tag| (acc << 8) | U256::from(*tag))
+        };
+        let minus_five = U256::MAX - U256::from(4);
+        let address = U256::from("0x8001");
+        let name = U256::from("0x6f70657261746f72000000000000000000000000000000000000000000000000");
+
+        assert_eq!(
+            format_typed_values(
+                type_tags(&[LOG_TYPE_UINT, LOG_TYPE_INT, LOG_TYPE_BOOL]),
+                &[U256::from(42), minus_five, U256::from(1)]
+            ),
+            vec!["42", "-5", "true"]
+        );
+        assert_eq!(
+            format_typed_values(
+                type_tags(&[LOG_TYPE_ADDRESS, LOG_TYPE_STRING, LOG_TYPE_BYTES32]),
+                &[address, name, U256::from(1)]
+            ),
+            vec![
+                "0x0000000000000000000000000000000000008001",
+                "operator",
+                "0x0000000000000000000000000000000000000000000000000000000000000001"
+            ]
+        );
+        // Values without a type tag are not logged.
+        assert_eq!(
+            format_typed_values(
+                type_tags(&[LOG_TYPE_UINT]),
+                &[U256::from(42), U256::from(1), U256::from(2)]
+            ),
+            vec!["42"]
+        );
+        assert_eq!(
+            format_typed_values(type_tags(&[LOG_TYPE_INT]), &[U256::from(7)]),
+            vec!["7"]
+        );
+    }
+
+    #[test]
+    fn test_assert_failure() {
+        let hook = TestVmHook::AssertEqFailed("1".into(), "2".into(), "Invalid sum".into());
+        assert_eq!(
+            hook.assert_failure().unwrap(),
+            "Assert failed: 1 is not equal to 2: Invalid sum"
+        );
+        let hook = TestVmHook::AssertGeFailed("1".into(), "2".into(), "Too small".into());
+        assert_eq!(
+            hook.assert_failure().unwrap(),
+            "Assert failed: 1 is not greater than or equal to 2: Too small"
+        );
+        let hook = TestVmHook::AssertApproxEqFailed(
+            "100".into(),
+            "110".into(),
+            "5".into(),
+            "Imprecise".into(),
+        );
+        assert_eq!(
+            hook.assert_failure().unwrap(),
+            "Assert failed: 100 is not within 5 of 110: Imprecise"
+        );
+        assert!(TestVmHook::TestStart("TEST_safeSub".into())
+            .assert_failure()
+            .is_none());
+    }
 }
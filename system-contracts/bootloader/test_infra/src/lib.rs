@@ -0,0 +1,28 @@
+//! Discovery and execution of the bootloader unittests from `bootloader_test.yul`.
+//!
+//! Used by the `test_infra` binary, and by the `cargo test` harness in `tests/bootloader.rs`.
+
+pub mod bootloader_hook;
+pub mod coverage;
+pub mod debug_info;
+pub mod differential;
+pub mod env_config;
+pub mod expected_failure;
+pub mod filter;
+pub mod fixtures;
+pub mod fuzz;
+pub mod gas_snapshot;
+pub mod gas_tests;
+mod hook;
+pub mod limits;
+mod memory_dump;
+pub mod output_snapshot;
+pub mod profiler;
+pub mod report;
+pub mod runner;
+pub mod scenario;
+pub mod state_fixtures;
+pub mod suite;
+mod test_count_tracer;
+mod tracer;
+pub mod tx_generator;
@@ -0,0 +1,60 @@
+/// Default cycle budget of a test run, far above what any of the tests needs.
+pub const DEFAULT_MAX_CYCLES: u32 = 50_000_000;
+
+/// Budgets of a single test run. A test that exceeds one of them is stopped and reported as timed out,
+/// so that a test that loops forever doesn't stall the whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TestLimits {
+    pub max_cycles: Option<u32>,
+    pub max_ergs: Option<u64>,
+}
+
+impl TestLimits {
+    /// Limits from the command line, where 0 means no limit.
+    pub fn new(max_cycles: u32, max_ergs: u64) -> Self {
+        Self {
+            max_cycles: (max_cycles > 0).then_some(max_cycles),
+            max_ergs: (max_ergs > 0).then_some(max_ergs),
+        }
+    }
+
+    /// Applies the limits requested by the test via `testing_setLimits`, where 0 keeps the current limit.
+    pub fn overridden_by(self, max_cycles: u32, max_ergs: u64) -> Self {
+        Self {
+            max_cycles: (max_cycles > 0).then_some(max_cycles).or(self.max_cycles),
+            max_ergs: (max_ergs > 0).then_some(max_ergs).or(self.max_ergs),
+        }
+    }
+
+    /// Returns the description of the exceeded limit, if any.
+    pub fn exceeded(&self, cycles: u32, ergs: u64) -> Option<String> {
+        if let Some(max_cycles) = self.max_cycles.filter(|max_cycles| cycles > *max_cycles) {
+            return Some(format!("used more than {} cycles", max_cycles));
+        }
+        if let Some(max_ergs) = self.max_ergs.filter(|max_ergs| ergs > *max_ergs) {
+            return Some(format!("used more than {} ergs", max_ergs));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let limits = TestLimits::new(1000, 0);
+        assert_eq!(limits.max_ergs, None);
+        assert_eq!(limits.exceeded(1000, u64::MAX), None);
+        assert_eq!(
+            limits.exceeded(1001, 0).unwrap(),
+            "used more than 1000 cycles"
+        );
+
+        let limits = limits.overridden_by(0, 500);
+        assert_eq!(limits.max_cycles, Some(1000));
+        assert_eq!(limits.exceeded(10, 501).unwrap(), "used more than 500 ergs");
+        assert_eq!(limits.overridden_by(5000, 0).exceeded(1001, 500), None);
+    }
+}
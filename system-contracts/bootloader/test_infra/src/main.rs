@@ -1,228 +1,721 @@
-use crate::{test_count_tracer::TestCountTracer, tracer::BootloaderTestTracer};
+use clap::{Parser, Subcommand};
 use colored::Colorize;
-use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
-use zksync_multivm::interface::{
-    L1BatchEnv, L2BlockEnv, SystemEnv, TxExecutionMode, InspectExecutionMode, VmFactory, VmInterface,
+use std::sync::{mpsc, Arc};
+use std::thread;
+use test_infra::{
+    bootloader_hook::HookVerbosity,
+    coverage::CoverageReport,
+    env_config::EnvArgs,
+    filter::TestFilter,
+    fixtures,
+    fuzz::{self, FuzzConfig},
+    gas_snapshot::{self, SnapshotComparison},
+    gas_tests::{self, GasTestExpectations},
+    limits::{self, TestLimits},
+    output_snapshot::{self, OutputSnapshots},
+    report::ReportTarget,
+    runner::{TestContext, TestOutcome},
+    scenario::{self, ScenarioOutcome},
+    suite::{load_bootloader, load_function_map, SuiteOptions, TestSuite},
+    tx_generator,
 };
-use zksync_multivm::vm_latest::{HistoryDisabled, ToTracerPointer, TracerDispatcher, Vm};
-use zksync_state::interface::{
-    InMemoryStorage, StoragePtr, StorageView, IN_MEMORY_STORAGE_DEFAULT_NETWORK_ID,
-};
-use zksync_types::fee_model::BatchFeeInput;
+use zksync_contracts::SystemContractsRepo;
+use zksync_multivm::interface::ExecutionResult;
+use zksync_types::U256;
 
-use std::{env, sync::Arc};
 use tracing_subscriber::fmt;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use zksync_contracts::{
-    BaseSystemContracts, ContractLanguage, SystemContractCode,
-    SystemContractsRepo,
-};
-use zksync_multivm::interface::{ExecutionResult, Halt};
-use zksync_types::system_contracts::get_system_smart_contracts_from_dir;
-use zksync_types::{block::L2BlockHasher, Address, L1BatchNumber, L2BlockNumber, U256, u256_to_h256, };
-use zksync_types::{L2ChainId, Transaction};
-use zksync_types::bytecode::BytecodeHash;
 
-mod hook;
-mod test_count_tracer;
-mod tracer;
+/// Runs the bootloader unittests from `bootloader_test.yul`.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Only run tests whose name matches this substring or regex.
+    #[arg(long, conflicts_with = "exact")]
+    filter: Option<String>,
+    /// Only run the test with exactly this name (e.g. `TEST_safeSub`).
+    #[arg(long)]
+    exact: Option<String>,
+    /// Print the names of the discovered tests without running them.
+    #[arg(long)]
+    list: bool,
+    /// Number of tests to run in parallel. Defaults to the number of available CPUs.
+    #[arg(long, short)]
+    jobs: Option<usize>,
+    /// Write per-test results to a file, as `junit=<path>` or `json=<path>`. Can be repeated.
+    #[arg(long = "report", value_name = "FORMAT=PATH")]
+    reports: Vec<ReportTarget>,
+    /// Compare the ergs, cycles, pubdata and storage writes of each test with the snapshot file,
+    /// and fail if any of them has grown beyond the tolerance.
+    #[arg(long)]
+    check_snapshot: bool,
+    /// Write the ergs, cycles, pubdata and storage writes of each test into the snapshot file.
+    #[arg(long, conflicts_with = "check_snapshot")]
+    update_snapshot: bool,
+    /// Snapshot file with the costs of each test. Defaults to `snapshots/bootloader_test.json`.
+    #[arg(long)]
+    snapshot_path: Option<PathBuf>,
+    /// Allowed increase of each cost (in percent) before it's reported as a regression.
+    #[arg(long, default_value_t = 0.0)]
+    snapshot_tolerance: f64,
+    /// Directory with the numbered transaction fixtures (`0.json`, `1.json`, ...) to push into the bootloader.
+    /// Defaults to `src/test_transactions`.
+    #[arg(long)]
+    tx_fixtures: Option<PathBuf>,
+    /// Directory with the initial state fixtures (`<name>.json` or `<name>.toml`) that the tests can request.
+    /// Defaults to `src/state_fixtures`.
+    #[arg(long)]
+    state_fixtures: Option<PathBuf>,
+    #[command(flatten)]
+    env: EnvArgs,
+    /// Record the bootloader instructions executed by the tests, and write the line and function coverage
+    /// of `bootloader.yul` to this LCOV file. Needs the assembly of the test bootloader (`yarn build:bootloader`).
+    #[arg(long, value_name = "LCOV_PATH")]
+    coverage: Option<PathBuf>,
+    /// Profile the cycles and ergs spent in the bootloader functions (and the called contracts) by each test, and
+    /// write them as folded stacks (`<test>.cycles.folded` and `<test>.ergs.folded`) into this directory.
+    #[arg(long, value_name = "DIR")]
+    profile: Option<PathBuf>,
+    /// Instead of running the tests, execute the transaction fixtures one by one with the production bootloader
+    /// (`proved_batch.yul`), and write their profile (`transactions.*.folded`) into the `--profile` directory.
+    #[arg(long, requires = "profile")]
+    profile_transactions: bool,
+    /// Number of runs of the fuzz tests (the tests that request random inputs via `testing_fuzzWord`).
+    #[arg(long, default_value_t = fuzz::DEFAULT_RUNS)]
+    fuzz_runs: u32,
+    /// Seed of the first run of the fuzz tests. Every next run uses the next seed.
+    #[arg(long, default_value_t = 0)]
+    fuzz_seed: u64,
+    /// Maximum number of runs spent on shrinking the inputs of a failed fuzz test.
+    #[arg(long, default_value_t = fuzz::DEFAULT_MAX_SHRINK_RUNS)]
+    fuzz_max_shrink_runs: u32,
+    /// Stop a test run that took more VM cycles than this, and report it as timed out. 0 means no limit.
+    #[arg(long, default_value_t = limits::DEFAULT_MAX_CYCLES)]
+    max_cycles: u32,
+    /// Stop a test run that spent more ergs than this, and report it as timed out. 0 means no limit.
+    #[arg(long, default_value_t = 0)]
+    max_ergs: u64,
+    /// Log the VM hooks of the bootloader itself with the test logs: `-v` for `debugLog` and `debugReturndata`,
+    /// `-vv` for all of them (validation, refunds, end of the transactions, ...).
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Compare the L2 to L1 logs, events and storage diffs of each passing test with its golden file
+    /// (`<test name>.json` in `--output-snapshots-dir`), and fail the test if they differ.
+    #[arg(long)]
+    output_snapshots: bool,
+    /// Rewrite the golden files with the outputs of this run.
+    #[arg(long)]
+    update_snapshots: bool,
+    /// Directory with the golden files. Defaults to `bootloader/tests/snapshots`.
+    #[arg(long)]
+    output_snapshots_dir: Option<PathBuf>,
+    /// Run each passing test on the fast VM as well, and fail it if the result, the ergs used or the
+    /// emitted logs, events and storage diffs differ from the run on the legacy VM. Fuzz tests are not compared, and
+    /// the asserts are only checked on the legacy VM.
+    #[arg(long)]
+    compare_fast_vm: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-// Executes bootloader unittests.
-fn execute_internal_bootloader_test() {
-    let artifacts_location_path = env::current_dir().unwrap().join("../build/artifacts");
-    let artifacts_location = artifacts_location_path
-        .to_str()
-        .expect("Invalid path: {artifacts_location_path:?}");
-    println!("Current dir is {:?}", artifacts_location);
-
-    let repo = SystemContractsRepo {
-        root: env::current_dir().unwrap().join("../../"),
-    };
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the gas tests from `bootloader/tests` (e.g. `transfer_test.yul`), which replace the whole bootloader,
+    /// and compare the words they return with the expectations file.
+    GasTests(GasTestsArgs),
+    /// Run the scenarios (`*.toml` or `*.json` in `src/scenarios`): batches of transactions that are executed by the
+    /// production bootloader (`proved_batch.yul`), with the expected status, refund and state after each of them.
+    Scenarios(ScenariosArgs),
+    /// Build transaction fixtures (`<index>.json`, like the ones in `src/test_transactions`) from a TOML or JSON spec,
+    /// signing the L2 transactions with the deterministic test keys.
+    GenerateTransactions(GenerateTransactionsArgs),
+}
 
-    let bytecode = repo.read_sys_contract_bytecode(artifacts_location, "bootloader_test", Some("Bootloader"), ContractLanguage::Yul);
-    let hash = BytecodeHash::for_bytecode(&bytecode).value();
-    let bootloader = SystemContractCode {
-        code: bytecode,
-        hash,
-    };
+#[derive(Debug, clap::Args)]
+struct GasTestsArgs {
+    /// File with the expected return values. Defaults to `src/gas_tests.json`.
+    #[arg(long)]
+    expectations: Option<PathBuf>,
+    /// Write the returned values into the expectations file instead of comparing them.
+    #[arg(long)]
+    update_expectations: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct ScenariosArgs {
+    /// Directory with the scenarios. Defaults to `src/scenarios`.
+    #[arg(long)]
+    dir: Option<PathBuf>,
+    /// Only run the scenarios whose name matches this substring or regex.
+    #[arg(long)]
+    filter: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct GenerateTransactionsArgs {
+    /// TOML (or JSON) file with the transactions to generate.
+    spec: PathBuf,
+    /// Directory to write the transactions into.
+    #[arg(long)]
+    out: PathBuf,
+    /// Index of the first written file. Defaults to the number of the numbered transactions already in the directory,
+    /// so that the new ones are appended.
+    #[arg(long)]
+    start: Option<usize>,
+}
+
+fn print_outcome(outcome: &TestOutcome) {
+    println!("\n === Running test {}", outcome.test_id);
+    for log in &outcome.logs {
+        println!("{} {}", "Test log".bold(), log);
+    }
+    match &outcome.result {
+        Ok(_) => match outcome.fuzz_runs {
+            Some(runs) => println!(
+                "{} {} ({} fuzz runs)",
+                "[PASS]".green(),
+                outcome.test_name,
+                runs
+            ),
+            None => println!("{} {}", "[PASS]".green(), outcome.test_name),
+        },
+        Err(error_info) => println!("{} {} {}", "[FAIL]".red(), outcome.test_name, error_info),
+    }
+    let stats = &outcome.stats;
+    println!(
+        "  ergs: {}, cycles: {}, pubdata: {}, storage writes: {}",
+        stats.ergs_used, stats.cycles_used, stats.pubdata_published, stats.storage_writes
+    );
+}
+
+fn print_snapshot_comparison(comparison: &SnapshotComparison) {
+    println!("\n ==== Gas snapshot ====");
+    for change in &comparison.improvements {
+        println!(
+            "{} {} {}: {} -> {} ({:.2}%)",
+            "[IMPROVED]".green(),
+            change.test_name,
+            change.metric,
+            change.old,
+            change.new,
+            change.percent()
+        );
+    }
+    for change in &comparison.regressions {
+        println!(
+            "{} {} {}: {} -> {} (+{:.2}%)",
+            "[REGRESSION]".red(),
+            change.test_name,
+            change.metric,
+            change.old,
+            change.new,
+            change.percent()
+        );
+    }
+    for test_name in &comparison.new_tests {
+        println!("{} {} is not in the snapshot", "[NEW]".yellow(), test_name);
+    }
+    for test_name in &comparison.removed_tests {
+        println!(
+            "{} {} is in the snapshot, but doesn't exist anymore",
+            "[REMOVED]".yellow(),
+            test_name
+        );
+    }
+    if comparison.regressions.is_empty() {
+        println!("{}", "No cost regressions.".green());
+    }
+}
+
+fn print_coverage(report: &CoverageReport, lcov_path: &Path) {
+    println!("\n ==== Coverage of bootloader.yul ====");
+    if report.lines.is_empty() {
+        println!("The assembly has no line info, so only the function coverage is reported.");
+    } else {
+        let covered = report.lines.values().filter(|hits| **hits > 0).count();
+        println!(
+            "Lines: {} of {} ({:.2}%)",
+            covered,
+            report.lines.len(),
+            covered as f64 * 100.0 / report.lines.len() as f64
+        );
+    }
+    let compiled = report
+        .functions
+        .iter()
+        .filter(|function| function.hits.is_some())
+        .count();
+    let covered = report
+        .functions
+        .iter()
+        .filter(|function| function.hits.is_some_and(|hits| hits > 0))
+        .count();
+    println!(
+        "Functions: {} of {} ({:.2}%)",
+        covered,
+        compiled,
+        covered as f64 * 100.0 / compiled.max(1) as f64
+    );
+    for function in report.uncovered_functions() {
+        println!(
+            "{} {} (bootloader.yul:{})",
+            "[UNCOVERED]".red(),
+            function.name,
+            function.first_line
+        );
+    }
+    let missing: Vec<&str> = report
+        .missing_functions()
+        .map(|function| function.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        println!(
+            "{} functions have no instructions of their own (inlined or removed by the optimizer): {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    println!("LCOV report written to {:?}", lcov_path);
+}
+
+// Executes the transaction fixtures with the production bootloader, and writes their profile.
+fn profile_transactions(
+    context: &TestContext,
+    repo: &SystemContractsRepo,
+    build_dir: &Path,
+    profile_dir: &Path,
+) {
+    let bootloader = load_bootloader(repo, &build_dir.join("artifacts"), "proved_batch");
+    let functions = load_function_map(build_dir, "proved_batch").unwrap_or_else(|err| {
+        println!("{}", err.red());
+        process::exit(1);
+    });
+    let functions = Arc::new(functions);
+
+    println!(
+        " ==== Profiling {} transactions ====",
+        context.transactions.len()
+    );
+    let (profile, results) = context.profile_transactions(bootloader, functions);
+    for (index, result) in results.iter().enumerate() {
+        match result {
+            ExecutionResult::Success { .. } => println!("{} {}", "[SUCCESS]".green(), index),
+            ExecutionResult::Revert { output } => println!(
+                "{} {} {}",
+                "[REVERT]".yellow(),
+                index,
+                output.to_user_friendly_string()
+            ),
+            ExecutionResult::Halt { reason } => println!("{} {} {}", "[HALT]".red(), index, reason),
+        }
+    }
+    profile
+        .write(profile_dir, "transactions")
+        .unwrap_or_else(|err| panic!("{}", err));
+    println!("Profile written to {:?}", profile_dir);
+}
 
+fn format_words(words: &[U256]) -> String {
+    let words: Vec<String> = words.iter().map(|word| format!("{:#x}", word)).collect();
+    format!("[{}]", words.join(", "))
+}
 
-    let bytecode = repo.read_sys_contract_bytecode("", "DefaultAccount", None, ContractLanguage::Sol);
-    let hash = BytecodeHash::for_bytecode(&bytecode).value();
-    let default_aa = SystemContractCode {
-        code: bytecode,
-        hash,
+// Runs the gas tests (Yul files replacing the whole bootloader), and compares what they return with the expectations.
+fn run_gas_tests(
+    context: &TestContext,
+    repo: &SystemContractsRepo,
+    artifacts_location: &Path,
+    args: &GasTestsArgs,
+) {
+    let exit_with = |err: String| -> ! {
+        println!("{}", err.red());
+        process::exit(1);
     };
+    let expectations_path = args
+        .expectations
+        .clone()
+        .unwrap_or_else(gas_tests::default_expectations_path);
+    let expectations =
+        gas_tests::load_expectations(&expectations_path).unwrap_or_else(|err| exit_with(err));
+    let names = gas_tests::gas_test_names(&gas_tests::default_gas_tests_dir())
+        .unwrap_or_else(|err| exit_with(err));
+
+    println!(" ==== Running {} gas tests ====", names.len());
+    let mut returned_values = GasTestExpectations::new();
+    let mut failed = vec![];
+    for name in &names {
+        let outcome = context.run_gas_test(load_bootloader(repo, artifacts_location, name));
+        let result = match (&outcome.result, outcome.returned) {
+            (ExecutionResult::Success { .. }, Some(returned)) => {
+                match (args.update_expectations, expectations.get(name)) {
+                    (false, Some(expected)) => gas_tests::check_returned(expected, &returned),
+                    (false, None) => Err(format!(
+                        "No expectation in {:?}, run with --update-expectations to add it",
+                        expectations_path
+                    )),
+                    (true, _) => Ok(()),
+                }
+                .map(|_| returned)
+            }
+            (ExecutionResult::Success { .. }, None) => Err("Didn't return any data".to_string()),
+            (ExecutionResult::Revert { output }, _) => {
+                Err(format!("Reverted: {}", output.to_user_friendly_string()))
+            }
+            (ExecutionResult::Halt { reason }, _) => Err(format!("Halted: {}", reason)),
+        };
+        match result {
+            Ok(returned) => {
+                println!("{} {} {}", "[PASS]".green(), name, format_words(&returned));
+                returned_values.insert(name.clone(), returned);
+            }
+            Err(err) => {
+                println!("{} {} {}", "[FAIL]".red(), name, err);
+                failed.push(name.clone());
+            }
+        }
+        let stats = &outcome.stats;
+        println!(
+            "  ergs: {}, cycles: {}, pubdata: {}, storage writes: {}",
+            stats.ergs_used, stats.cycles_used, stats.pubdata_published, stats.storage_writes
+        );
+    }
+
+    if !failed.is_empty() {
+        println!("{} {}", "FAILED gas tests:".red(), failed.join(", "));
+        process::exit(1);
+    }
+    if args.update_expectations {
+        gas_tests::write_expectations(&expectations_path, &returned_values)
+            .unwrap_or_else(|err| exit_with(err));
+        println!("Expectations written to {:?}", expectations_path);
+    } else {
+        println!("{}", "ALL gas tests passed.".green());
+    }
+}
+
+fn print_scenario_outcome(name: &str, outcome: &ScenarioOutcome) {
+    println!("\n === Running scenario {}", name);
+    for tx in &outcome.transactions {
+        let refund = tx
+            .refund
+            .map(|refund| format!(", refund {}", refund))
+            .unwrap_or_default();
+        match &tx.result {
+            Ok(()) => println!(
+                "{} block {} {}: {}{}",
+                "[PASS]".green(),
+                tx.block,
+                tx.name,
+                tx.status,
+                refund
+            ),
+            Err(err) => println!("{} block {} {}: {}", "[FAIL]".red(), tx.block, tx.name, err),
+        }
+    }
+    match &outcome.batch_result {
+        ExecutionResult::Success { .. } => println!("  batch: success"),
+        ExecutionResult::Revert { output } => {
+            println!("  batch: reverted {}", output.to_user_friendly_string())
+        }
+        ExecutionResult::Halt { reason } => println!("  batch: halted {}", reason),
+    }
+}
 
-    let base_system_contract = BaseSystemContracts {
-        bootloader,
-        default_aa,
-        evm_emulator: None,
+// Runs the scenarios with the production bootloader, and checks the outcome of each transaction.
+fn run_scenarios(
+    context: &TestContext,
+    repo: &SystemContractsRepo,
+    artifacts_location: &Path,
+    args: &ScenariosArgs,
+) {
+    let exit_with = |err: String| -> ! {
+        println!("{}", err.red());
+        process::exit(1);
     };
+    let dir = args
+        .dir
+        .clone()
+        .unwrap_or_else(scenario::default_scenarios_dir);
+    let scenarios = scenario::load_scenarios(&dir).unwrap_or_else(|err| exit_with(err));
+    let filter = TestFilter::new(None, args.filter.as_deref());
+    let names: Vec<&String> = scenarios
+        .keys()
+        .filter(|name| filter.matches(name))
+        .collect();
+    if names.is_empty() {
+        exit_with(format!("No scenarios to run in {:?}", dir));
+    }
 
-    let system_env = SystemEnv {
-        zk_porter_available: false,
-        version: zksync_types::ProtocolVersionId::latest(),
-        base_system_smart_contracts: base_system_contract,
-        bootloader_gas_limit: u32::MAX,
-        execution_mode: TxExecutionMode::VerifyExecute,
-        default_validation_computational_gas_limit: u32::MAX,
-        chain_id: zksync_types::L2ChainId::from(299),
+    let bootloader = load_bootloader(repo, artifacts_location, "proved_batch");
+    println!(" ==== Running {} scenarios ====", names.len());
+    let mut failed = vec![];
+    for name in names {
+        let outcome = context
+            .run_scenario(bootloader.clone(), &scenarios[name])
+            .unwrap_or_else(|err| exit_with(format!("Scenario {}: {}", name, err)));
+        print_scenario_outcome(name, &outcome);
+        if !outcome.passed() {
+            failed.push(name.clone());
+        }
+    }
+
+    if failed.is_empty() {
+        println!("{}", "ALL scenarios passed.".green());
+    } else {
+        println!("{} {}", "FAILED scenarios:".red(), failed.join(", "));
+        process::exit(1);
+    }
+}
+
+// Builds the transactions of the spec, and writes them as numbered fixtures.
+fn generate_transactions(args: &GenerateTransactionsArgs) {
+    let exit_with = |err: String| -> ! {
+        println!("{}", err.red());
+        process::exit(1);
+    };
+    let spec = tx_generator::load_tx_spec(&args.spec).unwrap_or_else(|err| exit_with(err));
+    let transactions =
+        tx_generator::generate_transactions(&spec).unwrap_or_else(|err| exit_with(err));
+    let start = match args.start {
+        Some(start) => start,
+        None if args.out.exists() => fixtures::numbered_fixture_paths(&args.out)
+            .unwrap_or_else(|err| exit_with(err))
+            .len(),
+        None => 0,
     };
+    let paths = tx_generator::write_transactions(&args.out, start, &transactions)
+        .unwrap_or_else(|err| exit_with(err));
+    for path in &paths {
+        println!("Wrote {:?}", path);
+    }
+}
+
+// Runs the tests on the thread pool, and prints their results in the order of `test_ids`
+// as soon as all the preceding tests are done.
+fn run_tests(
+    pool: &rayon::ThreadPool,
+    context: &TestContext,
+    test_ids: &[u32],
+) -> Vec<TestOutcome> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            pool.install(|| {
+                test_ids.par_iter().enumerate().for_each_with(
+                    sender,
+                    |sender, (index, &test_id)| {
+                        sender.send((index, context.run_test(test_id))).unwrap();
+                    },
+                )
+            })
+        });
+
+        let mut pending = BTreeMap::new();
+        let mut outcomes = Vec::with_capacity(test_ids.len());
+        for (index, outcome) in receiver {
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&outcomes.len()) {
+                print_outcome(&outcome);
+                outcomes.push(outcome);
+            }
+        }
+        outcomes
+    })
+}
 
-    let mut l1_batch_env = L1BatchEnv {
-        previous_batch_hash: None,
-        number: L1BatchNumber::from(1),
-        timestamp: 14,
-        fee_input: BatchFeeInput::sensible_l1_pegged_default(),
-        fee_account: Address::default(),
-
-        enforced_base_fee: None,
-        first_l2_block: L2BlockEnv {
-            number: 1,
-            timestamp: 15,
-            prev_block_hash: L2BlockHasher::legacy_hash(L2BlockNumber(0)),
-            max_virtual_blocks_to_create: 1,
-            interop_roots: vec![],
+// Executes bootloader unittests.
+fn execute_internal_bootloader_test(args: &Args) {
+    // The fixtures are generated without loading the bootloader and the system contracts.
+    if let Some(Command::GenerateTransactions(generate_args)) = &args.command {
+        generate_transactions(generate_args);
+        return;
+    }
+    let options = SuiteOptions {
+        tx_fixtures: args.tx_fixtures.clone(),
+        state_fixtures: args.state_fixtures.clone(),
+        env: args.env.clone(),
+        coverage: args.coverage.is_some(),
+        profile: args.profile.is_some() && !args.profile_transactions,
+        fuzz: FuzzConfig {
+            runs: args.fuzz_runs,
+            seed: args.fuzz_seed,
+            max_shrink_runs: args.fuzz_max_shrink_runs,
         },
+        limits: TestLimits::new(args.max_cycles, args.max_ergs),
+        hook_verbosity: HookVerbosity::from_count(args.verbose),
+        output_snapshots: (args.output_snapshots || args.update_snapshots).then(|| {
+            OutputSnapshots {
+                dir: args
+                    .output_snapshots_dir
+                    .clone()
+                    .unwrap_or_else(output_snapshot::default_output_snapshots_dir),
+                update: args.update_snapshots,
+            }
+        }),
+        compare_fast_vm: args.compare_fast_vm,
     };
+    let TestSuite {
+        context,
+        repo,
+        build_dir,
+        coverage_sources,
+    } = TestSuite::load(&options).unwrap_or_else(|err| {
+        println!("{}", err.red());
+        process::exit(1);
+    });
+    let artifacts_location_path = build_dir.join("artifacts");
+    println!("Current dir is {:?}", artifacts_location_path);
 
-    // First - get the number of tests.
-    let test_count = {
-        let storage: StoragePtr<StorageView<InMemoryStorage>> =
-            StorageView::new(InMemoryStorage::with_custom_system_contracts_and_chain_id(
-                L2ChainId::from(IN_MEMORY_STORAGE_DEFAULT_NETWORK_ID),
-                get_system_smart_contracts_from_dir(
-                    env::current_dir().unwrap().join("../../"),
-                ),
-            ))
-            .to_rc_ptr();
-
-        let mut vm: Vm<_, HistoryDisabled> =
-            VmFactory::new(l1_batch_env.clone(), system_env.clone(), storage.clone());
-
-        let test_count = Arc::new(OnceCell::default());
-        let custom_tracers = TestCountTracer::new(test_count.clone()).into_tracer_pointer();
-
-        // We're using a TestCountTracer (and passing 0 as fee account) - this should cause the bootloader
-        // test framework to report number of tests via VM hook.
-        let mut tracer_dispatcher = TracerDispatcher::from(custom_tracers);
-        vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
-
-        test_count.get().unwrap().clone()
-    };
-    println!(" ==== Running {} tests ====", test_count);
-
-    let mut tests_failed: u32 = 0;
-
-    // Now we iterate over the tests.
-    for test_id in 1..=test_count {
-        println!("\n === Running test {}", test_id);
-
-        let storage: StoragePtr<StorageView<InMemoryStorage>> =
-            StorageView::new(InMemoryStorage::with_custom_system_contracts_and_chain_id(
-                L2ChainId::from(IN_MEMORY_STORAGE_DEFAULT_NETWORK_ID),
-                get_system_smart_contracts_from_dir(
-                    env::current_dir().unwrap().join("../../"),
-                ),
-            ))
-            .to_rc_ptr();
-
-        // We are passing id of the test in location (0) where we normally put the operator.
-        // This is then picked up by the testing framework.
-        l1_batch_env.fee_account = zksync_types::H160::from(u256_to_h256(U256::from(test_id)));
-        let mut vm: Vm<_, HistoryDisabled> =
-            Vm::new(l1_batch_env.clone(), system_env.clone(), storage.clone());
-        let test_result = Arc::new(OnceCell::default());
-        let requested_assert = Arc::new(OnceCell::default());
-        let test_name = Arc::new(OnceCell::default());
-
-        let custom_tracers = BootloaderTestTracer::new(
-            test_result.clone(),
-            requested_assert.clone(),
-            test_name.clone(),
-        )
-        .into_tracer_pointer();
-        let mut tracer_dispatcher = TracerDispatcher::from(custom_tracers);
-
-        // Let's insert transactions into slots. They are not executed, but the tests can run functions against them.
-        let json_str = include_str!("test_transactions/0.json");
-        let tx: Transaction = serde_json::from_str(json_str).unwrap();
-        vm.push_transaction(tx);
-
-        let result = vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
-        drop(tracer_dispatcher);
-
-        let mut test_result = Arc::into_inner(test_result).unwrap().into_inner();
-        let requested_assert = Arc::into_inner(requested_assert).unwrap().into_inner();
-        let test_name = Arc::into_inner(test_name)
-            .unwrap()
-            .into_inner()
-            .unwrap_or_default();
+    if args.profile_transactions {
+        // Clap makes sure that `--profile` is set.
+        let profile_dir = args.profile.as_ref().unwrap();
+        profile_transactions(&context, &repo, &build_dir, profile_dir);
+        return;
+    }
+    match &args.command {
+        Some(Command::GasTests(gas_tests_args)) => {
+            run_gas_tests(&context, &repo, &artifacts_location_path, gas_tests_args);
+            return;
+        }
+        Some(Command::Scenarios(scenarios_args)) => {
+            run_scenarios(&context, &repo, &artifacts_location_path, scenarios_args);
+            return;
+        }
+        Some(Command::GenerateTransactions(_)) | None => {}
+    }
 
-        if test_result.is_none() {
-            test_result = Some(if let Some(requested_assert) = requested_assert {
-                match &result.result {
-                    ExecutionResult::Success { .. } => Err(format!(
-                        "Should have failed with {}, but run successfully.",
-                        requested_assert
-                    )),
-                    ExecutionResult::Revert { output } => Err(format!(
-                        "Should have failed with {}, but run reverted with {}.",
-                        requested_assert,
-                        output.to_user_friendly_string()
-                    )),
-                    ExecutionResult::Halt { reason } => {
-                        if let Halt::UnexpectedVMBehavior(reason) = reason {
-                            let reason = reason.strip_prefix("Assertion error: ").unwrap_or(reason);
-                            if reason == requested_assert {
-                                Ok(())
-                            } else {
-                                Err(format!(
-                                        "Should have failed with `{}`, but failed with different assert `{}`",
-                                        requested_assert, reason
-                                    ))
-                            }
-                        } else {
-                            Err(format!(
-                                "Should have failed with `{}`, but halted with`{}`",
-                                requested_assert, reason
-                            ))
-                        }
-                    }
-                }
-            } else {
-                match &result.result {
-                    ExecutionResult::Success { .. } => Ok(()),
-                    ExecutionResult::Revert { output } => Err(output.to_user_friendly_string()),
-                    ExecutionResult::Halt { reason } => Err(reason.to_string()),
-                }
-            });
+    let mut pool_builder = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = args.jobs {
+        pool_builder = pool_builder.num_threads(jobs);
+    }
+    let pool = pool_builder
+        .build()
+        .expect("Failed to build the thread pool");
+
+    // First - discover the tests. This also learns the setup each of them requests, so that it is only done once.
+    let test_names = pool.install(|| context.test_names());
+    let test_count = test_names.len() as u32;
+
+    let filter = TestFilter::new(args.exact.as_deref(), args.filter.as_deref());
+
+    if args.list {
+        for name in test_names.iter().filter(|name| filter.matches(name)) {
+            println!("{}", name);
         }
+        return;
+    }
+    let test_ids: Vec<u32> = (1..=test_count)
+        .zip(&test_names)
+        .filter(|(_, name)| filter.matches(name))
+        .map(|(test_id, _)| test_id)
+        .collect();
 
-        match &test_result.unwrap() {
-            Ok(_) => println!("{} {}", "[PASS]".green(), test_name),
-            Err(error_info) => {
-                tests_failed += 1;
-                println!("{} {} {}", "[FAIL]".red(), test_name, error_info)
+    if test_ids.is_empty() {
+        println!("{}", "No tests matched the filter.".red());
+        process::exit(1);
+    }
+    if filter.is_all() {
+        println!(" ==== Running {} tests ====", test_count);
+    } else {
+        println!(
+            " ==== Running {} of {} tests ====",
+            test_ids.len(),
+            test_count
+        );
+    }
+
+    let outcomes = run_tests(&pool, &context, &test_ids);
+    for report in &args.reports {
+        report.write(&outcomes).unwrap_or_else(|err| {
+            panic!("Failed to write the report to {:?}: {}", report.path, err)
+        });
+    }
+
+    if let (Some(lcov_path), Some(sources), Some(hits)) =
+        (&args.coverage, &coverage_sources, &context.coverage)
+    {
+        let report = sources.report(&hits.lock().unwrap());
+        fs::write(lcov_path, report.to_lcov(&sources.source_path)).unwrap_or_else(|err| {
+            panic!(
+                "Failed to write the coverage report to {:?}: {}",
+                lcov_path, err
+            )
+        });
+        print_coverage(&report, lcov_path);
+    }
+
+    if let Some(profile_dir) = &args.profile {
+        for outcome in &outcomes {
+            if let Some(profile) = &outcome.profile {
+                profile
+                    .write(profile_dir, &outcome.test_name)
+                    .unwrap_or_else(|err| panic!("{}", err));
             }
         }
+        println!("Profiles written to {:?}", profile_dir);
+    }
+
+    let snapshot_path = args
+        .snapshot_path
+        .clone()
+        .unwrap_or_else(gas_snapshot::default_snapshot_path);
+    let mut snapshot_regressions = 0;
+    let exit_with = |err: String| -> ! {
+        println!("{}", err.red());
+        process::exit(1);
+    };
+    if args.update_snapshot {
+        gas_snapshot::update(&snapshot_path, &outcomes, filter.is_all())
+            .unwrap_or_else(|err| exit_with(err));
+        println!("Snapshot written to {:?}", snapshot_path);
+    } else if args.check_snapshot {
+        let snapshot = gas_snapshot::load(&snapshot_path).unwrap_or_else(|err| exit_with(err));
+        let comparison = gas_snapshot::compare(
+            &snapshot,
+            &outcomes,
+            args.snapshot_tolerance,
+            filter.is_all(),
+        );
+        print_snapshot_comparison(&comparison);
+        snapshot_regressions = comparison.regressions.len();
     }
+
+    let tests_failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.result.is_err())
+        .count();
+
     if tests_failed > 0 {
         println!("{}", format!("{} tests failed.", tests_failed).red());
         process::exit(1);
+    } else if snapshot_regressions > 0 {
+        println!(
+            "{}",
+            format!(
+                "ALL tests passed, but {} costs regressed. Run with --update-snapshot if this is expected.",
+                snapshot_regressions
+            )
+            .red()
+        );
+        process::exit(1);
     } else {
         println!("{}", "ALL tests passed.".green())
     }
 }
 
 fn main() {
+    let args = Args::parse();
+
     tracing_subscriber::registry()
         .with(fmt::Layer::default())
         .with(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    execute_internal_bootloader_test();
+    execute_internal_bootloader_test(&args);
 }
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use zksync_types::{u256_to_h256, U256};
+
+/// Known regions of the bootloader heap, reported by the test via `testing_dumpMemory`.
+/// Maps the first byte of the region to its name (e.g. `TX_DESCRIPTION_BEGIN_BYTE`).
+pub type MemoryRegions = BTreeMap<u32, &'static str>;
+
+/// Names of the regions whose first bytes `testing_dumpMemory` passes to the hook, in the same order.
+const MEMORY_REGION_NAMES: [&str; 16] = [
+    "SCRATCH_SPACE_BEGIN_BYTE",
+    "PAYMASTER_CONTEXT_BEGIN_BYTE",
+    "CURRENT_L2_TX_HASHES_BEGIN_BYTE",
+    "NEW_FACTORY_DEPS_BEGIN_BYTE",
+    "TX_OPERATOR_REFUND_BEGIN_BYTE",
+    "TX_SUGGESTED_OVERHEAD_BEGIN_BYTE",
+    "TX_OPERATOR_TRUSTED_GAS_LIMIT_BEGIN_BYTE",
+    "TX_OPERATOR_L2_BLOCK_INFO_BEGIN_BYTE",
+    "INTEROP_ROOTS_PER_BLOCK_BEGIN_BYTE",
+    "INTEROP_ROOT_BEGIN_BYTE",
+    "COMPRESSED_BYTECODES_BEGIN_BYTE",
+    "PRIORITY_TXS_L1_DATA_BEGIN_BYTE",
+    "TXS_STATUS_ROLLING_HASH_BEGIN_BYTE",
+    "OPERATOR_PROVIDED_L1_MESSENGER_PUBDATA_BEGIN_BYTE",
+    "TX_DESCRIPTION_BEGIN_BYTE",
+    "TXS_IN_BATCH_LAST_PTR",
+];
+
+/// Names the first bytes of the regions passed by `testing_dumpMemory`. Returns `None` if the number of the regions
+/// doesn't match `MEMORY_REGION_NAMES`, i.e. the test utils and the test infra are out of sync.
+pub fn memory_regions(begin_bytes: &[U256]) -> Option<MemoryRegions> {
+    if begin_bytes.len() != MEMORY_REGION_NAMES.len() {
+        return None;
+    }
+    Some(
+        begin_bytes
+            .iter()
+            .map(|begin| begin.low_u32())
+            .zip(MEMORY_REGION_NAMES)
+            .collect(),
+    )
+}
+
+// Returns the region that the byte belongs to (the closest one starting at or before it),
+// as `<REGION> + <offset within the region>`.
+fn region_label(regions: &MemoryRegions, byte: u32) -> Option<String> {
+    let (begin, name) = regions.range(..=byte).next_back()?;
+    if *begin == byte {
+        Some(name.to_string())
+    } else {
+        Some(format!("{} + {}", name, byte - begin))
+    }
+}
+
+/// Formats the words dumped from the bootloader heap, one per line. `offset` is the byte offset of
+/// the first word, so it is always word-aligned.
+pub fn format_memory_dump(offset: u32, words: &[U256], regions: &MemoryRegions) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Memory dump of {} bytes at {}",
+        words.len() * 32,
+        offset
+    )];
+    for (byte, word) in (offset..).step_by(32).zip(words) {
+        let word = format!("0x{}", hex::encode(u256_to_h256(*word)));
+        match region_label(regions, byte) {
+            Some(label) => lines.push(format!("  {:>10} ({}): {}", byte, label, word)),
+            None => lines.push(format!("  {:>10}: {}", byte, word)),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_regions() {
+        let begin_bytes: Vec<U256> = (0..16u32).map(|index| U256::from(index * 1000)).collect();
+        let regions = memory_regions(&begin_bytes).unwrap();
+        assert_eq!(regions[&0], "SCRATCH_SPACE_BEGIN_BYTE");
+        assert_eq!(regions[&6000], "TX_OPERATOR_TRUSTED_GAS_LIMIT_BEGIN_BYTE");
+        assert_eq!(regions[&15000], "TXS_IN_BATCH_LAST_PTR");
+
+        assert!(memory_regions(&begin_bytes[1..]).is_none());
+    }
+
+    #[test]
+    fn test_format_memory_dump() {
+        let regions: MemoryRegions = [
+            (3840, "TX_OPERATOR_REFUND_BEGIN_BYTE"),
+            (4000, "TX_SUGGESTED_OVERHEAD_BEGIN_BYTE"),
+        ]
+        .into_iter()
+        .collect();
+        let lines = format_memory_dump(
+            3808,
+            &[U256::zero(), U256::from(0x1234), U256::one()],
+            &regions,
+        );
+
+        assert_eq!(
+            lines,
+            vec![
+                "Memory dump of 96 bytes at 3808".to_string(),
+                format!("        3808: 0x{}", "0".repeat(64)),
+                format!(
+                    "        3840 (TX_OPERATOR_REFUND_BEGIN_BYTE): 0x{}1234",
+                    "0".repeat(60)
+                ),
+                format!(
+                    "        3872 (TX_OPERATOR_REFUND_BEGIN_BYTE + 32): 0x{}1",
+                    "0".repeat(63)
+                ),
+            ]
+        );
+    }
+}
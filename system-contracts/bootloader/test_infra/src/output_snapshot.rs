@@ -0,0 +1,246 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use zksync_multivm::interface::VmExecutionLogs;
+use zksync_types::{l2_to_l1_log::L2ToL1Log, Address, H256};
+
+/// L2 to L1 log, either a system log (e.g. one of the `SystemLogKey`s set by the bootloader) or a user one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub tx_number_in_block: u16,
+    pub sender: String,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventEntry {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+/// Storage slot whose value at the end of the run differs from the initial one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageDiff {
+    pub address: String,
+    pub key: String,
+    pub initial_value: String,
+    pub final_value: String,
+}
+
+/// Protocol-level outputs of a test run, compared against the golden file of the test.
+/// Logs and events are kept in the order they were emitted, storage diffs are sorted by the slot,
+/// and all the values are hex-encoded, so that the file only changes if the outputs do.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputSnapshot {
+    pub system_logs: Vec<LogEntry>,
+    pub user_logs: Vec<LogEntry>,
+    pub events: Vec<EventEntry>,
+    pub storage_diffs: Vec<StorageDiff>,
+}
+
+fn hex_h256(value: &H256) -> String {
+    format!("0x{}", hex::encode(value.as_bytes()))
+}
+
+fn hex_address(address: &Address) -> String {
+    format!("0x{}", hex::encode(address.as_bytes()))
+}
+
+impl From<&L2ToL1Log> for LogEntry {
+    fn from(log: &L2ToL1Log) -> Self {
+        Self {
+            tx_number_in_block: log.tx_number_in_block,
+            sender: hex_address(&log.sender),
+            key: hex_h256(&log.key),
+            value: hex_h256(&log.value),
+        }
+    }
+}
+
+impl OutputSnapshot {
+    pub fn new(logs: &VmExecutionLogs) -> Self {
+        // Initial (before the first write) and final value of each written slot.
+        let mut slots = BTreeMap::new();
+        for log in logs.storage_logs.iter().filter(|log| log.log.is_write()) {
+            let slot = (*log.log.key.address(), *log.log.key.key());
+            slots
+                .entry(slot)
+                .or_insert((log.previous_value, log.log.value))
+                .1 = log.log.value;
+        }
+        Self {
+            system_logs: logs
+                .system_l2_to_l1_logs
+                .iter()
+                .map(|log| LogEntry::from(&log.0))
+                .collect(),
+            user_logs: logs
+                .user_l2_to_l1_logs
+                .iter()
+                .map(|log| LogEntry::from(&log.0))
+                .collect(),
+            events: logs
+                .events
+                .iter()
+                .map(|event| EventEntry {
+                    address: hex_address(&event.address),
+                    topics: event.indexed_topics.iter().map(hex_h256).collect(),
+                    data: format!("0x{}", hex::encode(&event.value)),
+                })
+                .collect(),
+            storage_diffs: slots
+                .into_iter()
+                .filter(|(_, (initial_value, final_value))| initial_value != final_value)
+                .map(
+                    |((address, key), (initial_value, final_value))| StorageDiff {
+                        address: hex_address(&address),
+                        key: hex_h256(&key),
+                        initial_value: hex_h256(&initial_value),
+                        final_value: hex_h256(&final_value),
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    // Entries of each section, serialized for the comparison.
+    fn sections(&self) -> [(&'static str, Vec<String>); 4] {
+        fn entries<T: Serialize>(entries: &[T]) -> Vec<String> {
+            entries
+                .iter()
+                .map(|entry| serde_json::to_string(entry).unwrap())
+                .collect()
+        }
+        [
+            ("system_logs", entries(&self.system_logs)),
+            ("user_logs", entries(&self.user_logs)),
+            ("events", entries(&self.events)),
+            ("storage_diffs", entries(&self.storage_diffs)),
+        ]
+    }
+
+    /// Describes how the outputs differ from the expected ones (e.g. of the golden file): the number of entries
+    /// and the first different entry of each section.
+    pub fn diff(&self, expected: &Self) -> Vec<String> {
+        let mut differences = vec![];
+        for ((section, actual), (_, expected)) in
+            self.sections().into_iter().zip(expected.sections())
+        {
+            if actual.len() != expected.len() {
+                differences.push(format!(
+                    "{}: {} entries, expected {}",
+                    section,
+                    actual.len(),
+                    expected.len()
+                ));
+            }
+            let first_difference = actual
+                .iter()
+                .zip(&expected)
+                .position(|(actual, expected)| actual != expected);
+            if let Some(index) = first_difference {
+                differences.push(format!(
+                    "{}[{}]: {}, expected {}",
+                    section, index, actual[index], expected[index]
+                ));
+            }
+        }
+        differences
+    }
+}
+
+/// Directory with the golden files of the tests (`<test_name>.json`).
+pub fn default_output_snapshots_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/snapshots")
+}
+
+pub fn load(path: &Path) -> Result<OutputSnapshot, String> {
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    serde_json::from_str(&data).map_err(|err| format!("Failed to parse {:?}: {}", path, err))
+}
+
+/// Where the golden files are, and whether they are rewritten or checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSnapshots {
+    pub dir: PathBuf,
+    pub update: bool,
+}
+
+impl OutputSnapshots {
+    pub fn path(&self, test_name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", test_name))
+    }
+
+    /// Compares the outputs of the test with its golden file, or rewrites the file if they are being updated.
+    pub fn check(&self, test_name: &str, snapshot: &OutputSnapshot) -> Result<(), String> {
+        let path = self.path(test_name);
+        if self.update {
+            // Unchanged files are not touched.
+            if path.exists() && load(&path).as_ref() == Ok(snapshot) {
+                return Ok(());
+            }
+            fs::create_dir_all(&self.dir)
+                .map_err(|err| format!("Failed to create {:?}: {}", self.dir, err))?;
+            let data = serde_json::to_string_pretty(snapshot).unwrap() + "\n";
+            return fs::write(&path, data)
+                .map_err(|err| format!("Failed to write {:?}: {}", path, err));
+        }
+        if !path.exists() {
+            return Err(format!(
+                "No output snapshot {:?}, run with --update-snapshots to create it",
+                path
+            ));
+        }
+        let expected = load(&path)?;
+        let differences = snapshot.diff(&expected);
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Outputs differ from the snapshot {:?} (run with --update-snapshots if this is expected): {}",
+                path,
+                differences.join("; ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(key: u8) -> LogEntry {
+        LogEntry {
+            tx_number_in_block: 0,
+            sender: "0x0000000000000000000000000000000000008008".into(),
+            key: format!("0x{:064x}", key),
+            value: format!("0x{:064x}", 1),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let expected = OutputSnapshot {
+            system_logs: vec![log(1), log(2)],
+            ..Default::default()
+        };
+        assert!(expected.diff(&expected).is_empty());
+
+        let actual = OutputSnapshot {
+            system_logs: vec![log(1), log(3), log(4)],
+            ..Default::default()
+        };
+        let differences = actual.diff(&expected);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(differences[0], "system_logs: 3 entries, expected 2");
+        assert!(differences[1].starts_with("system_logs[1]: {\"tx_number_in_block\":0,"));
+    }
+}
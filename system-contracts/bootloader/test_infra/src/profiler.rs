@@ -0,0 +1,265 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs, mem,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use zksync_multivm::interface::tracer::VmExecutionStopReason;
+use zksync_multivm::tracers::dynamic::vm_1_5_2::DynTracer;
+use zksync_multivm::vm_latest::{
+    BootloaderState, HistoryMode, SimpleMemory, VmTracer, ZkSyncVmState,
+};
+use zksync_multivm::zk_evm_latest::tracing::{BeforeExecutionData, VmLocalStateData};
+use zksync_state::interface::{StoragePtr, WriteStorage};
+use zksync_types::{Address, BOOTLOADER_ADDRESS};
+
+use crate::debug_info::FunctionMap;
+
+/// Resources spent in a single call stack (excluding the functions called from it).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StackCost {
+    pub cycles: u64,
+    pub ergs: u64,
+}
+
+/// Metric of the profile that is written into the folded stacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileMetric {
+    Cycles,
+    Ergs,
+}
+
+impl ProfileMetric {
+    pub const ALL: [ProfileMetric; 2] = [ProfileMetric::Cycles, ProfileMetric::Ergs];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProfileMetric::Cycles => "cycles",
+            ProfileMetric::Ergs => "ergs",
+        }
+    }
+
+    fn of(&self, cost: &StackCost) -> u64 {
+        match self {
+            ProfileMetric::Cycles => cost.cycles,
+            ProfileMetric::Ergs => cost.ergs,
+        }
+    }
+}
+
+/// Resources spent by the execution, keyed by the call stack (outermost frame first).
+/// Bootloader frames are the Yul functions, and the frames of other contracts are their addresses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub stacks: BTreeMap<Vec<String>, StackCost>,
+}
+
+impl Profile {
+    pub fn merge(&mut self, other: &Profile) {
+        for (stack, cost) in &other.stacks {
+            let total = self.stacks.entry(stack.clone()).or_default();
+            total.cycles += cost.cycles;
+            total.ergs += cost.ergs;
+        }
+    }
+
+    /// Formats the profile as folded stacks (`frame;frame;frame <value>` lines), as read by
+    /// `flamegraph.pl`, inferno or speedscope.
+    pub fn to_folded(&self, metric: ProfileMetric) -> String {
+        let mut folded = String::new();
+        for (stack, cost) in &self.stacks {
+            let value = metric.of(cost);
+            if value > 0 {
+                writeln!(folded, "{} {}", stack.join(";"), value).unwrap();
+            }
+        }
+        folded
+    }
+
+    /// Writes the profile into `<dir>/<name>.cycles.folded` and `<dir>/<name>.ergs.folded`.
+    pub fn write(&self, dir: &Path, name: &str) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {:?}: {}", dir, err))?;
+        for metric in ProfileMetric::ALL {
+            let path = dir.join(format!("{}.{}.folded", name, metric.name()));
+            fs::write(&path, self.to_folded(metric))
+                .map_err(|err| format!("Failed to write {:?}: {}", path, err))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Frame {
+    /// Bootloader code outside of any function.
+    Bootloader,
+    BootloaderFunction(usize),
+    Contract(Address),
+}
+
+// The instruction executed before the current one. Its ergs are only known once the next one starts.
+struct PreviousInstruction {
+    depth: usize,
+    ergs_remaining: u32,
+    ergs_price: u32,
+}
+
+/// Tracer that attributes the cycles and ergs of every executed instruction to its call stack.
+/// The ergs of an instruction are the decrease of the frame's ergs until the next instruction, so they include
+/// the dynamic costs (e.g. of storage writes). Calls and returns, which move ergs between frames, are counted
+/// with the base price of the opcode.
+/// The profile is added to the shared one at the end of the run.
+pub struct ProfilerTracer {
+    functions: Arc<FunctionMap>,
+    stacks: HashMap<Vec<Frame>, StackCost>,
+    stack: Vec<Frame>,
+    previous_stack: Vec<Frame>,
+    previous: Option<PreviousInstruction>,
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl ProfilerTracer {
+    pub fn new(functions: Arc<FunctionMap>, profile: Arc<Mutex<Profile>>) -> Self {
+        Self {
+            functions,
+            stacks: HashMap::new(),
+            stack: vec![],
+            previous_stack: vec![],
+            previous: None,
+            profile,
+        }
+    }
+
+    fn add_cost(&mut self, stack: &[Frame], cycles: u64, ergs: u64) {
+        if let Some(cost) = self.stacks.get_mut(stack) {
+            cost.cycles += cycles;
+            cost.ergs += ergs;
+        } else {
+            self.stacks
+                .insert(stack.to_vec(), StackCost { cycles, ergs });
+        }
+    }
+
+    fn frame_name(&self, frame: Frame) -> String {
+        match frame {
+            Frame::Bootloader => "bootloader".to_string(),
+            Frame::BootloaderFunction(function) => self.functions.name(function).to_string(),
+            Frame::Contract(address) => format!("{:?}", address),
+        }
+    }
+}
+
+impl<S, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for ProfilerTracer {
+    fn before_execution(
+        &mut self,
+        state: VmLocalStateData<'_>,
+        data: BeforeExecutionData,
+        _memory: &SimpleMemory<H>,
+        _storage: StoragePtr<S>,
+    ) {
+        let callstack = &state.vm_local_state.callstack;
+        let mut stack = mem::take(&mut self.stack);
+        stack.clear();
+        for entry in callstack.inner.iter().chain([&callstack.current]) {
+            if entry.code_address.is_zero() {
+                continue;
+            }
+            if entry.code_address == BOOTLOADER_ADDRESS {
+                // Every near call of the bootloader is a call of a Yul function.
+                stack.push(match self.functions.function_at(entry.pc) {
+                    Some(function) => Frame::BootloaderFunction(function),
+                    None => Frame::Bootloader,
+                });
+            } else if !entry.is_local_frame {
+                // Near calls within other contracts are not resolved.
+                stack.push(Frame::Contract(entry.code_address));
+            }
+        }
+
+        let depth = callstack.inner.len();
+        let ergs_remaining = callstack.current.ergs_remaining;
+        if let Some(previous) = self.previous.take() {
+            let ergs = if previous.depth == depth {
+                previous.ergs_remaining.saturating_sub(ergs_remaining)
+            } else {
+                previous.ergs_price
+            };
+            let previous_stack = mem::take(&mut self.previous_stack);
+            self.add_cost(&previous_stack, 0, ergs as u64);
+            self.previous_stack = previous_stack;
+        }
+        self.add_cost(&stack, 1, 0);
+
+        self.previous = Some(PreviousInstruction {
+            depth,
+            ergs_remaining,
+            ergs_price: data.opcode.variant.ergs_price(),
+        });
+        self.stack = mem::replace(&mut self.previous_stack, stack);
+    }
+}
+
+impl<S: WriteStorage, H: HistoryMode> VmTracer<S, H> for ProfilerTracer {
+    fn after_vm_execution(
+        &mut self,
+        _state: &mut ZkSyncVmState<S, H>,
+        _bootloader_state: &BootloaderState,
+        _stop_reason: VmExecutionStopReason,
+    ) {
+        if let Some(previous) = self.previous.take() {
+            let previous_stack = mem::take(&mut self.previous_stack);
+            self.add_cost(&previous_stack, 0, previous.ergs_price as u64);
+        }
+
+        let mut profile = Profile::default();
+        for (stack, cost) in mem::take(&mut self.stacks) {
+            let stack = stack
+                .into_iter()
+                .map(|frame| self.frame_name(frame))
+                .collect();
+            profile.stacks.insert(stack, cost);
+        }
+        self.profile.lock().unwrap().merge(&profile);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_folded() {
+        let stack = |frames: &[&str]| frames.iter().map(|frame| frame.to_string()).collect();
+        let mut profile = Profile::default();
+        profile.stacks.insert(
+            stack(&["bootloader", "processTx"]),
+            StackCost {
+                cycles: 10,
+                ergs: 0,
+            },
+        );
+        profile.stacks.insert(
+            stack(&["bootloader", "processTx", "getGasPrice"]),
+            StackCost {
+                cycles: 3,
+                ergs: 20,
+            },
+        );
+        let mut other = Profile::default();
+        other.stacks.insert(
+            stack(&["bootloader", "processTx"]),
+            StackCost { cycles: 5, ergs: 7 },
+        );
+        profile.merge(&other);
+
+        assert_eq!(
+            profile.to_folded(ProfileMetric::Cycles),
+            "bootloader;processTx 15\nbootloader;processTx;getGasPrice 3\n"
+        );
+        assert_eq!(
+            profile.to_folded(ProfileMetric::Ergs),
+            "bootloader;processTx 7\nbootloader;processTx;getGasPrice 20\n"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::{fmt::Write as _, fs, path::PathBuf, str::FromStr, time::Duration};
+
+use serde::Serialize;
+
+use crate::runner::{TestOutcome, TestStats};
+
+/// Name of the test suite in the reports.
+const SUITE_NAME: &str = "bootloader_test";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Junit,
+    Json,
+}
+
+/// Report requested on the command line, as `<format>=<path>` (e.g. `junit=target/bootloader.xml`).
+#[derive(Debug, Clone)]
+pub struct ReportTarget {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for ReportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `<format>=<path>`, got `{}`", s))?;
+        let format = match format {
+            "junit" => ReportFormat::Junit,
+            "json" => ReportFormat::Json,
+            _ => {
+                return Err(format!(
+                    "Unknown report format `{}`, expected `junit` or `json`",
+                    format
+                ))
+            }
+        };
+        if path.is_empty() {
+            return Err("Report path is empty".to_string());
+        }
+        Ok(Self {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl ReportTarget {
+    pub fn write(&self, outcomes: &[TestOutcome]) -> std::io::Result<()> {
+        let report = match self.format {
+            ReportFormat::Junit => junit_report(outcomes),
+            ReportFormat::Json => json_report(outcomes),
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, report)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonReport<'a> {
+    tests: usize,
+    failures: usize,
+    duration_secs: f64,
+    results: Vec<JsonTestResult<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonTestResult<'a> {
+    name: &'a str,
+    passed: bool,
+    failure: Option<&'a str>,
+    requested_assert: Option<&'a str>,
+    logs: &'a [String],
+    stats: &'a TestStats,
+    duration_secs: f64,
+}
+
+fn total_duration(outcomes: &[TestOutcome]) -> Duration {
+    outcomes.iter().map(|outcome| outcome.duration).sum()
+}
+
+fn failures(outcomes: &[TestOutcome]) -> usize {
+    outcomes
+        .iter()
+        .filter(|outcome| outcome.result.is_err())
+        .count()
+}
+
+fn json_report(outcomes: &[TestOutcome]) -> String {
+    let report = JsonReport {
+        tests: outcomes.len(),
+        failures: failures(outcomes),
+        duration_secs: total_duration(outcomes).as_secs_f64(),
+        results: outcomes
+            .iter()
+            .map(|outcome| JsonTestResult {
+                name: &outcome.test_name,
+                passed: outcome.result.is_ok(),
+                failure: outcome.result.as_ref().err().map(String::as_str),
+                requested_assert: outcome.requested_assert.as_deref(),
+                logs: &outcome.logs,
+                stats: &outcome.stats,
+                duration_secs: outcome.duration.as_secs_f64(),
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&report).expect("Failed to serialize the report")
+}
+
+fn xml_escape(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn junit_report(outcomes: &[TestOutcome]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let tests = outcomes.len();
+    let failures = failures(outcomes);
+    let time = total_duration(outcomes).as_secs_f64();
+
+    writeln!(
+        xml,
+        "<testsuites tests=\"{tests}\" failures=\"{failures}\" time=\"{time:.3}\">"
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "  <testsuite name=\"{SUITE_NAME}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" time=\"{time:.3}\">"
+    )
+    .unwrap();
+    for outcome in outcomes {
+        writeln!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"{SUITE_NAME}\" time=\"{:.3}\">",
+            xml_escape(&outcome.test_name),
+            outcome.duration.as_secs_f64()
+        )
+        .unwrap();
+        let stats = &outcome.stats;
+        xml.push_str("      <properties>\n");
+        if let Some(requested_assert) = &outcome.requested_assert {
+            writeln!(
+                xml,
+                "        <property name=\"requested_assert\" value=\"{}\"/>",
+                xml_escape(requested_assert)
+            )
+            .unwrap();
+        }
+        for (name, value) in [
+            ("ergs_used", stats.ergs_used),
+            ("cycles_used", stats.cycles_used.into()),
+            ("pubdata_published", stats.pubdata_published.into()),
+            ("storage_writes", stats.storage_writes as u64),
+        ] {
+            writeln!(xml, "        <property name=\"{name}\" value=\"{value}\"/>").unwrap();
+        }
+        xml.push_str("      </properties>\n");
+        if let Err(failure) = &outcome.result {
+            let failure = xml_escape(failure);
+            writeln!(
+                xml,
+                "      <failure message=\"{failure}\">{failure}</failure>"
+            )
+            .unwrap();
+        }
+        if !outcome.logs.is_empty() {
+            writeln!(
+                xml,
+                "      <system-out>{}</system-out>",
+                xml_escape(&outcome.logs.join("\n"))
+            )
+            .unwrap();
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn outcomes() -> Vec<TestOutcome> {
+        vec![
+            TestOutcome {
+                test_id: 1,
+                test_name: "TEST_safeSub".to_string(),
+                result: Ok(()),
+                requested_assert: None,
+                logs: vec!["Base Fee 250000000".to_string()],
+                stats: TestStats {
+                    ergs_used: 1234,
+                    cycles_used: 567,
+                    pubdata_published: 0,
+                    storage_writes: 2,
+                },
+                profile: None,
+                fuzz_runs: None,
+                duration: Duration::from_millis(1500),
+            },
+            TestOutcome {
+                test_id: 2,
+                test_name: "TEST_safeSubAssert".to_string(),
+                result: Err("Assert failed: 1 is not equal to 2: <wrong>".to_string()),
+                requested_assert: Some("willFail".to_string()),
+                logs: vec![],
+                stats: TestStats::default(),
+                profile: None,
+                fuzz_runs: None,
+                duration: Duration::from_millis(500),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_parse_report_target() {
+        let target: ReportTarget = "junit=target/report.xml".parse().unwrap();
+        assert_eq!(target.format, ReportFormat::Junit);
+        assert_eq!(target.path, PathBuf::from("target/report.xml"));
+
+        assert!("xml=report.xml".parse::<ReportTarget>().is_err());
+        assert!("json".parse::<ReportTarget>().is_err());
+        assert!("json=".parse::<ReportTarget>().is_err());
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let xml = junit_report(&outcomes());
+        assert!(xml.contains("<testsuite name=\"bootloader_test\" tests=\"2\" failures=\"1\" errors=\"0\" time=\"2.000\">"));
+        assert!(xml.contains(
+            "<testcase name=\"TEST_safeSub\" classname=\"bootloader_test\" time=\"1.500\">"
+        ));
+        assert!(xml.contains("<system-out>Base Fee 250000000</system-out>"));
+        assert!(xml.contains("<property name=\"requested_assert\" value=\"willFail\"/>"));
+        assert!(xml.contains("<property name=\"ergs_used\" value=\"1234\"/>"));
+        assert!(
+            xml.contains("<failure message=\"Assert failed: 1 is not equal to 2: &lt;wrong&gt;\">")
+        );
+    }
+
+    #[test]
+    fn test_json_report() {
+        let json: serde_json::Value = serde_json::from_str(&json_report(&outcomes())).unwrap();
+        assert_eq!(json["tests"], 2);
+        assert_eq!(json["failures"], 1);
+        assert_eq!(json["results"][0]["passed"], true);
+        assert_eq!(json["results"][0]["failure"], serde_json::Value::Null);
+        assert_eq!(json["results"][0]["logs"][0], "Base Fee 250000000");
+        assert_eq!(json["results"][0]["stats"]["cycles_used"], 567);
+        assert_eq!(json["results"][1]["requested_assert"], "willFail");
+        assert_eq!(
+            json["results"][1]["failure"],
+            "Assert failed: 1 is not equal to 2: <wrong>"
+        );
+    }
+}
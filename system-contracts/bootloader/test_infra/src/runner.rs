@@ -0,0 +1,792 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use zksync_contracts::SystemContractCode;
+use zksync_multivm::interface::{
+    ExecutionResult, InspectExecutionMode, L1BatchEnv, L2BlockEnv, SystemEnv,
+    VmExecutionResultAndLogs, VmFactory, VmInterface,
+};
+use zksync_multivm::vm_fast;
+use zksync_multivm::vm_latest::{HistoryDisabled, ToTracerPointer, TracerDispatcher, Vm};
+use zksync_state::interface::{ImmutableStorageView, InMemoryStorage, StoragePtr, StorageView};
+use zksync_types::{block::L2BlockHasher, u256_to_h256, L2BlockNumber, Transaction, H160, U256};
+
+use crate::{
+    bootloader_hook::HookVerbosity,
+    coverage::{CoverageTracer, PcHits},
+    debug_info::FunctionMap,
+    differential,
+    env_config::{EnvConfig, Envs},
+    expected_failure::ExpectedFailure,
+    fixtures::FixtureSets,
+    fuzz::{self, FuzzConfig, FuzzInputs, FuzzWords},
+    gas_tests::ReturnDataTracer,
+    limits::TestLimits,
+    output_snapshot::{OutputSnapshot, OutputSnapshots},
+    profiler::{Profile, ProfilerTracer},
+    scenario::{self, EndedTx, Scenario, ScenarioOutcome, ScenarioTracer, TxOutcome, TxStatus},
+    state_fixtures::{StateFixture, StateFixtures},
+    test_count_tracer::TestCountTracer,
+    tracer::{BootloaderTestTracer, RequestedSetup},
+};
+
+/// Everything that is needed to run a single bootloader test.
+/// Each test gets its own copy of the storage and VM, so the context can be shared between threads.
+pub struct TestContext {
+    pub l1_batch_env: L1BatchEnv,
+    pub system_env: SystemEnv,
+    /// Initial state (with system contracts) that is cloned for every test.
+    pub storage: InMemoryStorage,
+    /// Transactions that are pushed into the bootloader memory, in order.
+    pub transactions: Vec<Transaction>,
+    /// Named transaction sets, that replace `transactions` for the tests requesting them.
+    pub fixture_sets: FixtureSets,
+    /// Named initial states, applied on top of `storage` for the tests requesting them.
+    pub state_fixtures: StateFixtures,
+    /// Named environments, applied on top of `system_env` and `l1_batch_env` for the tests requesting them.
+    pub envs: Envs,
+    /// If set, the executed bootloader instructions of all the tests are collected here.
+    pub coverage: Option<Arc<Mutex<PcHits>>>,
+    /// If set, each test is profiled, with the bootloader frames resolved to the functions of the test bootloader.
+    pub profiler: Option<Arc<FunctionMap>>,
+    /// Runs and seed of the tests that request random inputs via `testing_fuzzWord`.
+    pub fuzz: FuzzConfig,
+    /// Budgets of each test run, which the tests can change via `testing_setLimits`.
+    pub limits: TestLimits,
+    /// Which of the bootloader's own VM hooks (e.g. `debugLog`) are added to the logs of the tests.
+    pub hook_verbosity: HookVerbosity,
+    /// If set, the logs, events and storage diffs of each test are compared with (or written into) its golden file.
+    pub output_snapshots: Option<OutputSnapshots>,
+    /// If set, each passing test is run on the fast VM as well, and fails if the runs diverge.
+    pub compare_fast_vm: bool,
+    /// Names of the tests and the setup they request, discovered once for all the tests.
+    pub(crate) discovered_tests: OnceCell<Vec<DiscoveredTest>>,
+}
+
+/// Resources spent by a single test run (including the bootloader's own setup before the test body).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestStats {
+    pub ergs_used: u64,
+    pub cycles_used: u32,
+    pub pubdata_published: u32,
+    pub storage_writes: usize,
+}
+
+impl TestStats {
+    fn new(result: &VmExecutionResultAndLogs) -> Self {
+        Self {
+            ergs_used: result.statistics.gas_used,
+            cycles_used: result.statistics.cycles_used,
+            pubdata_published: result.statistics.pubdata_published,
+            storage_writes: result
+                .logs
+                .storage_logs
+                .iter()
+                .filter(|log| log.log.is_write())
+                .count(),
+        }
+    }
+}
+
+/// Result of a single bootloader test.
+#[derive(Debug)]
+pub struct TestOutcome {
+    pub test_id: u32,
+    pub test_name: String,
+    pub result: Result<(), String>,
+    /// Assert that the test requested via `testing_testWillFailWith`.
+    pub requested_assert: Option<String>,
+    /// Lines logged by the test via `testing_log`.
+    pub logs: Vec<String>,
+    pub stats: TestStats,
+    /// Cycles and ergs per call stack, if the tests are profiled.
+    pub profile: Option<Profile>,
+    /// Number of runs, if the test is a fuzz test.
+    pub fuzz_runs: Option<u32>,
+    pub duration: Duration,
+}
+
+/// Result of a single gas test.
+#[derive(Debug)]
+pub struct GasTestOutcome {
+    pub result: ExecutionResult,
+    /// Words returned by the bootloader, if it returned.
+    pub returned: Option<Vec<U256>>,
+    pub stats: TestStats,
+}
+
+/// Everything that is set up before the test starts.
+#[derive(Debug, Clone, Copy, Default)]
+struct TestSetup<'a> {
+    /// Transactions that are pushed into the bootloader memory, in order.
+    transactions: &'a [Transaction],
+    state_fixture: Option<&'a StateFixture>,
+    env: Option<&'a EnvConfig>,
+}
+
+/// Names of the fixtures and environment requested by the test.
+#[derive(Debug, Clone, Default)]
+struct RequestedSetupNames {
+    fixture: Option<String>,
+    state_fixture: Option<String>,
+    env: Option<String>,
+}
+
+/// Test, as reported by its discovery run.
+#[derive(Debug, Clone)]
+pub(crate) struct DiscoveredTest {
+    name: String,
+    setup: RequestedSetupNames,
+}
+
+impl From<RequestedSetup> for RequestedSetupNames {
+    fn from(setup: RequestedSetup) -> Self {
+        let into_name = |cell: Arc<OnceCell<String>>| Arc::into_inner(cell).unwrap().into_inner();
+        Self {
+            fixture: into_name(setup.fixture),
+            state_fixture: into_name(setup.state_fixture),
+            env: into_name(setup.env),
+        }
+    }
+}
+
+impl TestContext {
+    fn new_storage(
+        &self,
+        state_fixture: Option<&StateFixture>,
+    ) -> StoragePtr<StorageView<InMemoryStorage>> {
+        let mut storage = self.storage.clone();
+        if let Some(state_fixture) = state_fixture {
+            state_fixture.apply(&mut storage);
+        }
+        StorageView::new(storage).to_rc_ptr()
+    }
+
+    // Environment of the test run, with the requested environment applied.
+    fn test_env(&self, test_id: u32, setup: TestSetup<'_>) -> (L1BatchEnv, SystemEnv) {
+        let mut system_env = self.system_env.clone();
+        let mut l1_batch_env = self.l1_batch_env.clone();
+        if let Some(env) = setup.env {
+            env.apply(&mut system_env, &mut l1_batch_env);
+        }
+        // We are passing id of the test in location (0) where we normally put the operator.
+        // This is then picked up by the testing framework.
+        l1_batch_env.fee_account = H160::from(u256_to_h256(U256::from(test_id)));
+        (l1_batch_env, system_env)
+    }
+
+    fn new_vm(
+        &self,
+        test_id: u32,
+        setup: TestSetup<'_>,
+    ) -> Vm<StorageView<InMemoryStorage>, HistoryDisabled> {
+        let (l1_batch_env, system_env) = self.test_env(test_id, setup);
+        Vm::new(
+            l1_batch_env,
+            system_env,
+            self.new_storage(setup.state_fixture),
+        )
+    }
+
+    /// Returns the number of tests in the bootloader test file.
+    pub fn test_count(&self) -> u32 {
+        // We're using a TestCountTracer (and passing 0 as fee account) - this should cause the bootloader
+        // test framework to report number of tests via VM hook.
+        let mut vm = self.new_vm(0, TestSetup::default());
+
+        let test_count = Arc::new(OnceCell::default());
+        let custom_tracers = TestCountTracer::new(test_count.clone()).into_tracer_pointer();
+
+        let mut tracer_dispatcher = TracerDispatcher::from(custom_tracers);
+        vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
+
+        *test_count.get().unwrap()
+    }
+
+    // Runs the test only until its setup is done, to learn its name and the fixtures, state and
+    // environment it requests.
+    fn discover_test(&self, test_id: u32) -> DiscoveredTest {
+        let (_, output) = self.execute(test_id, TestSetup::default(), true, FuzzInputs::default());
+        DiscoveredTest {
+            name: output
+                .test_name
+                .unwrap_or_else(|| panic!("Test {} did not report its name", test_id)),
+            setup: output.requested_setup,
+        }
+    }
+
+    // Discovers all the tests on the first call, in parallel on the current rayon thread pool.
+    fn discovered_tests(&self) -> &[DiscoveredTest] {
+        self.discovered_tests.get_or_init(|| {
+            (1..=self.test_count())
+                .into_par_iter()
+                .map(|test_id| self.discover_test(test_id))
+                .collect()
+        })
+    }
+
+    /// Returns the names of all tests, ordered by their ids (starting from 1).
+    /// The tests are discovered once, in parallel on the current rayon thread pool, so this should be called
+    /// before running them.
+    pub fn test_names(&self) -> Vec<String> {
+        self.discovered_tests()
+            .iter()
+            .map(|test| test.name.clone())
+            .collect()
+    }
+
+    // Runs the test with the given setup.
+    fn execute(
+        &self,
+        test_id: u32,
+        setup: TestSetup<'_>,
+        discovery: bool,
+        fuzz_inputs: FuzzInputs,
+    ) -> (VmExecutionResultAndLogs, TracerOutput) {
+        let mut vm = self.new_vm(test_id, setup);
+        let test_result = Arc::new(OnceCell::default());
+        let requested_assert = Arc::new(OnceCell::default());
+        let test_name = Arc::new(OnceCell::default());
+        let test_logs = Arc::new(Mutex::new(Vec::new()));
+        let requested_setup = RequestedSetup::default();
+        let fuzz_inputs = Arc::new(Mutex::new(fuzz_inputs));
+
+        let mut tracer = BootloaderTestTracer::new(
+            test_result.clone(),
+            requested_assert.clone(),
+            test_name.clone(),
+            test_logs.clone(),
+            requested_setup.clone(),
+        )
+        .with_fuzz_inputs(fuzz_inputs.clone())
+        .with_limits(self.limits)
+        .with_hook_verbosity(self.hook_verbosity);
+        if discovery {
+            tracer = tracer.discovery_mode();
+        }
+        let mut tracers = vec![tracer.into_tracer_pointer()];
+        // Discovery runs only execute the setup of the test, so they'd just inflate the hits.
+        if let Some(coverage) = self.coverage.as_ref().filter(|_| !discovery) {
+            tracers.push(CoverageTracer::new(coverage.clone()).into_tracer_pointer());
+        }
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        if let Some(functions) = self.profiler.as_ref().filter(|_| !discovery) {
+            tracers.push(
+                ProfilerTracer::new(functions.clone(), profile.clone()).into_tracer_pointer(),
+            );
+        }
+        let mut tracer_dispatcher = TracerDispatcher::from(tracers);
+
+        // Let's insert transactions into slots. They are not executed, but the tests can run functions against them.
+        for tx in setup.transactions {
+            vm.push_transaction(tx.clone());
+        }
+
+        let result = vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
+        drop(tracer_dispatcher);
+
+        let output = TracerOutput {
+            test_result: Arc::into_inner(test_result).unwrap().into_inner(),
+            requested_assert: Arc::into_inner(requested_assert).unwrap().into_inner(),
+            test_name: Arc::into_inner(test_name).unwrap().into_inner(),
+            logs: Arc::into_inner(test_logs).unwrap().into_inner().unwrap(),
+            requested_setup: requested_setup.into(),
+            profile: self
+                .profiler
+                .as_ref()
+                .map(|_| Arc::into_inner(profile).unwrap().into_inner().unwrap()),
+            fuzz_words: Arc::into_inner(fuzz_inputs)
+                .unwrap()
+                .into_inner()
+                .unwrap()
+                .into_used(),
+        };
+        (result, output)
+    }
+
+    // Runs the test on the fast VM, without any tracers. The testing hooks are not handled there,
+    // so the run is only compared with the one on the legacy VM.
+    fn execute_on_fast_vm(&self, test_id: u32, setup: TestSetup<'_>) -> VmExecutionResultAndLogs {
+        let (l1_batch_env, system_env) = self.test_env(test_id, setup);
+        let mut vm: vm_fast::Vm<ImmutableStorageView<InMemoryStorage>> = vm_fast::Vm::new(
+            l1_batch_env,
+            system_env,
+            self.new_storage(setup.state_fixture),
+        );
+        for tx in setup.transactions {
+            vm.push_transaction(tx.clone());
+        }
+        vm.inspect(&mut Default::default(), InspectExecutionMode::Bootloader)
+    }
+
+    // Creates the VM with a different bootloader than the test one.
+    fn new_vm_with_bootloader(
+        &self,
+        bootloader: SystemContractCode,
+    ) -> Vm<StorageView<InMemoryStorage>, HistoryDisabled> {
+        let mut system_env = self.system_env.clone();
+        system_env.base_system_smart_contracts.bootloader = bootloader;
+        Vm::new(
+            self.l1_batch_env.clone(),
+            system_env,
+            self.new_storage(None),
+        )
+    }
+
+    /// Executes the transactions one by one with the given bootloader (e.g. `proved_batch`) instead of running the
+    /// tests, and profiles the execution. Returns the profile of all the transactions, and their results.
+    pub fn profile_transactions(
+        &self,
+        bootloader: SystemContractCode,
+        functions: Arc<FunctionMap>,
+    ) -> (Profile, Vec<ExecutionResult>) {
+        let mut vm = self.new_vm_with_bootloader(bootloader);
+
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        let mut results = vec![];
+        for tx in &self.transactions {
+            vm.push_transaction(tx.clone());
+            let tracer = ProfilerTracer::new(functions.clone(), profile.clone());
+            let mut tracer_dispatcher = TracerDispatcher::from(tracer.into_tracer_pointer());
+            let result = vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::OneTx);
+            results.push(result.result);
+        }
+        let profile = Arc::into_inner(profile).unwrap().into_inner().unwrap();
+        (profile, results)
+    }
+
+    /// Executes the transactions of the scenario as a single batch with the given bootloader (`proved_batch`),
+    /// and checks the outcome of each of them.
+    pub fn run_scenario(
+        &self,
+        bootloader: SystemContractCode,
+        scenario: &Scenario,
+    ) -> Result<ScenarioOutcome, String> {
+        let state_fixture = scenario
+            .state_fixture
+            .as_ref()
+            .map(|name| {
+                self.state_fixtures.get(name).ok_or_else(|| {
+                    format!(
+                        "Unknown state fixture `{}`, available: {:?}",
+                        name,
+                        self.state_fixtures.keys().collect::<Vec<_>>()
+                    )
+                })
+            })
+            .transpose()?;
+        let mut system_env = self.system_env.clone();
+        let mut l1_batch_env = self.l1_batch_env.clone();
+        scenario.env.apply(&mut system_env, &mut l1_batch_env);
+        system_env.base_system_smart_contracts.bootloader = bootloader;
+
+        let mut block_env = l1_batch_env.first_l2_block.clone();
+        if let Some(first_block) = scenario.blocks.first() {
+            block_env.timestamp = first_block.timestamp.unwrap_or(block_env.timestamp);
+            block_env.max_virtual_blocks_to_create = first_block
+                .max_virtual_blocks
+                .unwrap_or(block_env.max_virtual_blocks_to_create);
+        }
+        l1_batch_env.first_l2_block = block_env.clone();
+        let version = system_env.version;
+        let mut vm: Vm<_, HistoryDisabled> =
+            Vm::new(l1_batch_env, system_env, self.new_storage(state_fixture));
+
+        // Hash of the last pushed block, which the bootloader checks when the next one starts.
+        let mut last_block_hash = None;
+        for block in &scenario.blocks {
+            if let Some(prev_block_hash) = last_block_hash {
+                block_env = L2BlockEnv {
+                    number: block_env.number + 1,
+                    timestamp: block.timestamp.unwrap_or(block_env.timestamp + 1),
+                    prev_block_hash,
+                    max_virtual_blocks_to_create: block.max_virtual_blocks.unwrap_or(1),
+                    interop_roots: vec![],
+                };
+                vm.start_new_l2_block(block_env.clone());
+            }
+            let mut hasher = L2BlockHasher::new(
+                L2BlockNumber(block_env.number),
+                block_env.timestamp,
+                block_env.prev_block_hash,
+            );
+            for tx in &block.transactions {
+                hasher.push_tx_hash(tx.transaction.hash());
+                vm.push_transaction(tx.transaction.clone());
+            }
+            last_block_hash = Some(hasher.finalize(version));
+        }
+
+        let ended = Arc::new(Mutex::new(Vec::new()));
+        let expected_states = scenario
+            .transactions()
+            .map(|tx| tx.expected.state.clone())
+            .collect();
+        let tracer = ScenarioTracer::new(expected_states, ended.clone());
+        let mut tracer_dispatcher = TracerDispatcher::from(tracer.into_tracer_pointer());
+        let batch_result = vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
+        drop(tracer_dispatcher);
+        let ended: Vec<EndedTx> = Arc::into_inner(ended).unwrap().into_inner().unwrap();
+
+        let mut transactions = vec![];
+        for (block_number, block) in (1..).zip(&scenario.blocks) {
+            for tx in &block.transactions {
+                let index = transactions.len();
+                let ended_tx = ended.get(index);
+                let mut result = scenario::check_tx(&tx.expected, ended_tx);
+                // The first transaction that hasn't ended is the one that halted the batch.
+                if index == ended.len() {
+                    if let (Err(err), ExecutionResult::Halt { reason }) =
+                        (&mut result, &batch_result.result)
+                    {
+                        *err = format!("{} (batch halted: {})", err, reason);
+                    }
+                }
+                transactions.push(TxOutcome {
+                    block: block_number,
+                    name: tx.name.clone(),
+                    status: ended_tx.map_or(TxStatus::Rejected, EndedTx::status),
+                    refund: ended_tx.and_then(|ended_tx| ended_tx.refund),
+                    result,
+                });
+            }
+        }
+        Ok(ScenarioOutcome {
+            transactions,
+            batch_result: batch_result.result,
+        })
+    }
+
+    /// Executes a gas test, i.e. a Yul file that replaces the whole bootloader (e.g. `transfer_test.yul`).
+    pub fn run_gas_test(&self, bootloader: SystemContractCode) -> GasTestOutcome {
+        let mut vm = self.new_vm_with_bootloader(bootloader);
+
+        let returned = Arc::new(OnceCell::default());
+        let tracer = ReturnDataTracer::new(returned.clone());
+        let mut tracer_dispatcher = TracerDispatcher::from(tracer.into_tracer_pointer());
+        let result = vm.inspect(&mut tracer_dispatcher, InspectExecutionMode::Bootloader);
+        drop(tracer_dispatcher);
+
+        GasTestOutcome {
+            stats: TestStats::new(&result),
+            result: result.result,
+            returned: Arc::into_inner(returned).unwrap().into_inner(),
+        }
+    }
+
+    /// Runs a single test.
+    pub fn run_test(&self, test_id: u32) -> TestOutcome {
+        let started_at = Instant::now();
+
+        // Tests can request a fixture set, initial state or environment, which have to be set up before
+        // the test starts. So they are taken from the discovery run of the test.
+        let requested = &self.discovered_tests()[test_id as usize - 1].setup;
+        let setup = TestSetup {
+            transactions: requested
+                .fixture
+                .as_ref()
+                .and_then(|fixture| self.fixture_sets.get(fixture))
+                .unwrap_or(&self.transactions),
+            state_fixture: requested
+                .state_fixture
+                .as_ref()
+                .and_then(|state_fixture| self.state_fixtures.get(state_fixture)),
+            env: requested.env.as_ref().and_then(|env| self.envs.get(env)),
+        };
+
+        let (mut result, vm_result, mut output) =
+            self.run_with_setup(test_id, setup, requested, FuzzInputs::new(self.fuzz.seed));
+        let test_name = output.test_name.clone().unwrap_or_default();
+
+        // Tests that requested random inputs are run again with other seeds, until one of the runs fails.
+        let fuzz_runs = (!output.fuzz_words.is_empty()).then_some(self.fuzz.runs.max(1));
+        if let Some(runs) = fuzz_runs {
+            let failed_run = match result {
+                Ok(()) => (1..runs).find_map(|run| {
+                    let seed = self.fuzz.run_seed(run);
+                    let (result, _, output) =
+                        self.run_with_setup(test_id, setup, requested, FuzzInputs::new(seed));
+                    result.err().map(|_| (run, seed, output.fuzz_words))
+                }),
+                Err(_) => Some((0, self.fuzz.seed, output.fuzz_words.clone())),
+            };
+            if let Some((run, seed, words)) = failed_run {
+                let shrunk = fuzz::shrink(words.clone(), self.fuzz.max_shrink_runs, |words| {
+                    let inputs = FuzzInputs::with_overrides(seed, words.clone());
+                    self.run_with_setup(test_id, setup, requested, inputs)
+                        .0
+                        .is_err()
+                });
+                // The logs and the failure are reported for the shrunk inputs.
+                let inputs = FuzzInputs::with_overrides(seed, shrunk);
+                let (shrunk_result, _, shrunk_output) =
+                    self.run_with_setup(test_id, setup, requested, inputs);
+                output.logs = vec![
+                    format!(
+                        "Fuzz run {} of {} failed with seed {}, inputs {}",
+                        run + 1,
+                        runs,
+                        seed,
+                        fuzz::format_words(&words)
+                    ),
+                    format!(
+                        "Reproduce with: --exact {} --fuzz-seed {} --fuzz-runs 1",
+                        test_name, seed
+                    ),
+                ];
+                output.logs.extend(shrunk_output.logs);
+                result = shrunk_result.map_err(|failure| {
+                    format!(
+                        "{} (shrunk inputs {})",
+                        failure,
+                        fuzz::format_words(&shrunk_output.fuzz_words)
+                    )
+                });
+            }
+        }
+
+        // Outputs of the fuzz tests depend on their inputs, so they are not compared with the snapshots,
+        // nor with the fast VM (which doesn't get the inputs).
+        if result.is_ok() && fuzz_runs.is_none() {
+            if let Some(snapshots) = &self.output_snapshots {
+                result = snapshots.check(&test_name, &OutputSnapshot::new(&vm_result.logs));
+            }
+        }
+        // The fast VM doesn't have the limits, so the runs that were stopped on a timeout can't be compared.
+        let timed_out = matches!(output.requested_assert, Some(ExpectedFailure::Timeout(_)));
+        if result.is_ok() && fuzz_runs.is_none() && !timed_out && self.compare_fast_vm {
+            let fast_vm_result = self.execute_on_fast_vm(test_id, setup);
+            let divergences = differential::divergences(&vm_result, &fast_vm_result);
+            if !divergences.is_empty() {
+                result = Err(format!(
+                    "Diverged on the fast VM: {}",
+                    divergences.join("; ")
+                ));
+            }
+        }
+
+        TestOutcome {
+            test_id,
+            test_name,
+            result,
+            requested_assert: output
+                .requested_assert
+                .map(|requested_assert| requested_assert.to_string()),
+            logs: output.logs,
+            stats: TestStats::new(&vm_result),
+            profile: output.profile,
+            fuzz_runs,
+            duration: started_at.elapsed(),
+        }
+    }
+
+    // Runs the test with the setup requested by the discovery run, and checks its result.
+    fn run_with_setup(
+        &self,
+        test_id: u32,
+        setup: TestSetup<'_>,
+        requested: &RequestedSetupNames,
+        fuzz_inputs: FuzzInputs,
+    ) -> (Result<(), String>, VmExecutionResultAndLogs, TracerOutput) {
+        let (vm_result, mut output) = self.execute(test_id, setup, false, fuzz_inputs);
+
+        let used = &output.requested_setup;
+        let result = check_fixture(
+            "testing_useFixture",
+            &self.fixture_sets,
+            requested.fixture.as_deref(),
+            used.fixture.as_deref(),
+        )
+        .and_then(|()| {
+            check_fixture(
+                "testing_useState",
+                &self.state_fixtures,
+                requested.state_fixture.as_deref(),
+                used.state_fixture.as_deref(),
+            )
+        })
+        .and_then(|()| {
+            check_fixture(
+                "testing_useEnv",
+                &self.envs,
+                requested.env.as_deref(),
+                used.env.as_deref(),
+            )
+        })
+        .and_then(|()| {
+            output.test_result.take().unwrap_or_else(|| {
+                check_execution_result(&vm_result.result, output.requested_assert.as_ref())
+            })
+        });
+        (result, vm_result, output)
+    }
+}
+
+/// Values collected by the [`BootloaderTestTracer`] during a single run.
+struct TracerOutput {
+    test_result: Option<Result<(), String>>,
+    requested_assert: Option<ExpectedFailure>,
+    test_name: Option<String>,
+    logs: Vec<String>,
+    requested_setup: RequestedSetupNames,
+    profile: Option<Profile>,
+    /// Words requested via `testing_fuzzWord`, empty if the test is not a fuzz test.
+    fuzz_words: FuzzWords,
+}
+
+// Checks that the fixture (or environment) `used` by the test via the `hook` during the run
+// is the one that was `requested` by its discovery run, and thus set up.
+fn check_fixture<T>(
+    hook: &str,
+    fixtures: &BTreeMap<String, T>,
+    requested: Option<&str>,
+    used: Option<&str>,
+) -> Result<(), String> {
+    if let Some(requested) = requested {
+        if !fixtures.contains_key(requested) {
+            return Err(format!(
+                "Unknown `{}` requested via {}, available: {:?}",
+                requested,
+                hook,
+                fixtures.keys().collect::<Vec<_>>()
+            ));
+        }
+    }
+    if requested != used {
+        return Err(format!(
+            "{} must be called at the start of the test, before other testing hooks",
+            hook
+        ));
+    }
+    Ok(())
+}
+
+// Checks the outcome of the test run, for tests that didn't fail on any of the asserts.
+fn check_execution_result(
+    result: &ExecutionResult,
+    requested_assert: Option<&ExpectedFailure>,
+) -> Result<(), String> {
+    if let Some(requested_assert) = requested_assert {
+        requested_assert.check(result)
+    } else {
+        match result {
+            ExecutionResult::Success { .. } => Ok(()),
+            ExecutionResult::Revert { output } => Err(output.to_user_friendly_string()),
+            ExecutionResult::Halt { reason } => Err(reason.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hook::TestVmHook;
+
+    // Feeds the hooks to a tracer in discovery mode, like the discovery run of a test that triggers them in order.
+    fn discover(hooks: &[TestVmHook]) -> (Option<String>, RequestedSetupNames) {
+        let test_name = Arc::new(OnceCell::default());
+        let requested_setup = RequestedSetup::default();
+        let mut tracer = BootloaderTestTracer::new(
+            Arc::default(),
+            Arc::default(),
+            test_name.clone(),
+            Arc::default(),
+            requested_setup.clone(),
+        )
+        .discovery_mode();
+        for hook in hooks {
+            tracer.handle_discovery_hook(hook);
+        }
+        drop(tracer);
+        (
+            Arc::into_inner(test_name).unwrap().into_inner(),
+            requested_setup.into(),
+        )
+    }
+
+    fn names(names: &[&str]) -> BTreeMap<String, ()> {
+        names.iter().map(|name| (name.to_string(), ())).collect()
+    }
+
+    #[test]
+    fn test_setup_hook_after_other_hooks() {
+        let (test_name, requested) = discover(&[
+            TestVmHook::TestStart("TEST_lateEnv".to_string()),
+            TestVmHook::TestLog("value".to_string(), "1".to_string()),
+            TestVmHook::UseEnv("high_fees".to_string()),
+        ]);
+        assert_eq!(test_name.as_deref(), Some("TEST_lateEnv"));
+        // The discovery stops at the log, so the environment is not set up.
+        assert_eq!(requested.env, None);
+
+        // The run then reaches the hook without the environment in place.
+        let err = check_fixture(
+            "testing_useEnv",
+            &names(&["high_fees"]),
+            requested.env.as_deref(),
+            Some("high_fees"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "testing_useEnv must be called at the start of the test, before other testing hooks"
+        );
+    }
+
+    #[test]
+    fn test_fixture_state_and_env() {
+        let (test_name, requested) = discover(&[
+            TestVmHook::TestStart("TEST_fullSetup".to_string()),
+            TestVmHook::UseFixture("eip712_transfer".to_string()),
+            TestVmHook::UseState("funded_account".to_string()),
+            TestVmHook::UseEnv("high_fees".to_string()),
+            TestVmHook::AssertEqFailed(
+                "1".to_string(),
+                "2".to_string(),
+                "after the setup".to_string(),
+            ),
+            TestVmHook::UseFixture("other".to_string()),
+        ]);
+        assert_eq!(test_name.as_deref(), Some("TEST_fullSetup"));
+        assert_eq!(requested.fixture.as_deref(), Some("eip712_transfer"));
+        assert_eq!(requested.state_fixture.as_deref(), Some("funded_account"));
+        assert_eq!(requested.env.as_deref(), Some("high_fees"));
+
+        // The run uses the same setup.
+        for (hook, available, requested) in [
+            (
+                "testing_useFixture",
+                names(&["eip712_transfer"]),
+                &requested.fixture,
+            ),
+            (
+                "testing_useState",
+                names(&["funded_account"]),
+                &requested.state_fixture,
+            ),
+            ("testing_useEnv", names(&["high_fees"]), &requested.env),
+        ] {
+            assert_eq!(
+                check_fixture(hook, &available, requested.as_deref(), requested.as_deref()),
+                Ok(())
+            );
+        }
+
+        let err = check_fixture(
+            "testing_useState",
+            &names(&["other_state"]),
+            requested.state_fixture.as_deref(),
+            requested.state_fixture.as_deref(),
+        )
+        .unwrap_err();
+        assert!(
+            err.starts_with("Unknown `funded_account` requested via testing_useState"),
+            "{}",
+            err
+        );
+    }
+}
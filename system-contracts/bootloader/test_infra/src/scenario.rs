@@ -0,0 +1,584 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+use zksync_multivm::interface::ExecutionResult;
+use zksync_multivm::tracers::dynamic::vm_1_5_2::DynTracer;
+use zksync_multivm::vm_latest::{HistoryMode, SimpleMemory, VmTracer};
+use zksync_multivm::zk_evm_latest::tracing::{BeforeExecutionData, VmLocalStateData};
+use zksync_state::interface::{ReadStorage, StoragePtr, WriteStorage};
+use zksync_types::{
+    bytecode::BytecodeHash, get_code_key, get_nonce_key, h256_to_u256, u256_to_h256,
+    utils::storage_key_for_eth_balance, AccountTreeId, StorageKey, Transaction, U256,
+};
+
+use crate::{
+    bootloader_hook::BootloaderHook, env_config::EnvConfig, hook::TestVmHook,
+    state_fixtures::StateFixture, tx_generator,
+};
+
+/// How the bootloader handled a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Executed, and the execution succeeded.
+    #[default]
+    Success,
+    /// Included in the batch, but the execution reverted.
+    Failure,
+    /// Not included: the bootloader halted the batch before the transaction ended (e.g. its validation failed).
+    Rejected,
+}
+
+impl fmt::Display for TxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => write!(f, "success"),
+            Self::Failure => write!(f, "failure"),
+            Self::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+/// What a transaction of the scenario is expected to do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxExpectation {
+    pub status: TxStatus,
+    /// Gas refunded to the user (as reported to the operator by the bootloader).
+    pub refund: Option<U256>,
+    /// Balances, nonces, bytecodes and storage slots right after the transaction has ended.
+    /// Only the listed values are checked.
+    pub state: StateFixture,
+}
+
+// Transaction, as written in the scenario file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TxEntry {
+    /// Transaction JSON (like the fixtures in `test_transactions`), or a TOML spec of a single transaction
+    /// (see `tx_generator`), relative to the scenario file.
+    file: PathBuf,
+    #[serde(default)]
+    status: TxStatus,
+    refund: Option<U256>,
+    #[serde(default)]
+    state: StateFixture,
+}
+
+// L2 block, as written in the scenario file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockEntry {
+    timestamp: Option<u64>,
+    max_virtual_blocks: Option<u32>,
+    transactions: Vec<TxEntry>,
+}
+
+// Scenario, as written in the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    state_fixture: Option<String>,
+    #[serde(default)]
+    env: EnvConfig,
+    blocks: Vec<BlockEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScenarioTx {
+    /// Name of the transaction file, used in the reports.
+    pub name: String,
+    pub transaction: Transaction,
+    pub expected: TxExpectation,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScenarioBlock {
+    /// Defaults to the timestamp of the previous block + 1 (or the one of the first block of the batch env).
+    pub timestamp: Option<u64>,
+    /// Defaults to 1 (or the one of the first block of the batch env).
+    pub max_virtual_blocks: Option<u32>,
+    pub transactions: Vec<ScenarioTx>,
+}
+
+/// Batch of transactions that is executed by the `proved_batch` bootloader, with the expected outcome of each of them.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    /// Initial state applied on top of the default storage, by the name of the state fixture.
+    pub state_fixture: Option<String>,
+    /// Overrides of the batch environment of the run.
+    pub env: EnvConfig,
+    pub blocks: Vec<ScenarioBlock>,
+}
+
+impl Scenario {
+    /// All the transactions of the batch, in order.
+    pub fn transactions(&self) -> impl Iterator<Item = &ScenarioTx> {
+        self.blocks.iter().flat_map(|block| &block.transactions)
+    }
+}
+
+/// Scenarios, keyed by the name of the file (without the extension).
+pub type Scenarios = BTreeMap<String, Scenario>;
+
+/// Directory with the scenarios that are run by the `scenarios` command.
+pub fn default_scenarios_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/scenarios")
+}
+
+fn parse_scenario_file(path: &Path, data: &str) -> Result<ScenarioFile, String> {
+    let parsed: Result<ScenarioFile, String> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(data).map_err(|err| err.to_string()),
+        _ => serde_json::from_str(data).map_err(|err| err.to_string()),
+    };
+    let file = parsed.map_err(|err| format!("Failed to parse scenario {:?}: {}", path, err))?;
+    if file.blocks.is_empty() {
+        return Err(format!("Scenario {:?} has no blocks", path));
+    }
+    if let Some(index) = file
+        .blocks
+        .iter()
+        .position(|block| block.transactions.is_empty())
+    {
+        return Err(format!(
+            "Block {} of scenario {:?} has no transactions",
+            index + 1,
+            path
+        ));
+    }
+    Ok(file)
+}
+
+fn load_transaction(path: &Path) -> Result<Transaction, String> {
+    // Specs are generated (and the L2 transactions signed) when the scenario is loaded.
+    if path.extension().and_then(|ext| ext.to_str()) == Some("toml") {
+        let spec = tx_generator::load_tx_spec(path)?;
+        let mut transactions = tx_generator::generate_transactions(&spec)
+            .map_err(|err| format!("{:?}: {}", path, err))?;
+        if transactions.len() != 1 {
+            return Err(format!(
+                "{:?} has {} transactions, a scenario entry must have exactly one",
+                path,
+                transactions.len()
+            ));
+        }
+        return Ok(transactions.remove(0));
+    }
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    serde_json::from_str(&data)
+        .map_err(|err| format!("Failed to parse transaction {:?}: {}", path, err))
+}
+
+/// Loads the scenario, with the transaction files resolved relative to its directory.
+pub fn load_scenario(path: &Path) -> Result<Scenario, String> {
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    let file = parse_scenario_file(path, &data)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let blocks = file
+        .blocks
+        .into_iter()
+        .map(|block| {
+            let transactions = block
+                .transactions
+                .into_iter()
+                .map(|tx| {
+                    Ok(ScenarioTx {
+                        name: tx.file.display().to_string(),
+                        transaction: load_transaction(&dir.join(&tx.file))?,
+                        expected: TxExpectation {
+                            status: tx.status,
+                            refund: tx.refund,
+                            state: tx.state,
+                        },
+                    })
+                })
+                .collect::<Result<_, String>>()?;
+            Ok(ScenarioBlock {
+                timestamp: block.timestamp,
+                max_virtual_blocks: block.max_virtual_blocks,
+                transactions,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Scenario {
+        state_fixture: file.state_fixture,
+        env: file.env,
+        blocks,
+    })
+}
+
+/// Loads all the `*.json` and `*.toml` scenarios from the directory.
+/// Other JSON files (e.g. the transactions of the scenarios) can be kept in its subdirectories.
+pub fn load_scenarios(dir: &Path) -> Result<Scenarios, String> {
+    let read_error =
+        |err: std::io::Error| format!("Failed to read scenarios from {:?}: {}", dir, err);
+
+    let mut scenarios = Scenarios::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if !path.is_file()
+            || !matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("json" | "toml")
+            )
+        {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid scenario file name {:?}", path))?
+            .to_string();
+        if scenarios
+            .insert(name.clone(), load_scenario(&path)?)
+            .is_some()
+        {
+            return Err(format!(
+                "Duplicate scenario `{}` in {:?}, there are both .json and .toml files",
+                name, dir
+            ));
+        }
+    }
+    Ok(scenarios)
+}
+
+/// Compares the values of the state with the expected ones. `read` returns the current value of the slot.
+pub fn check_state(
+    expected: &StateFixture,
+    mut read: impl FnMut(&StorageKey) -> U256,
+) -> Vec<String> {
+    let mut errors = vec![];
+    for (address, account) in &expected.accounts {
+        if let Some(balance) = account.balance {
+            let actual = read(&storage_key_for_eth_balance(address));
+            if actual != balance {
+                errors.push(format!(
+                    "balance of {:?} is {:#x}, expected {:#x}",
+                    address, actual, balance
+                ));
+            }
+        }
+        if account.nonce.is_some() || account.deployment_nonce.is_some() {
+            // The NonceHolder keeps both nonces in a single slot: deployment_nonce * 2^128 + nonce.
+            let raw_nonce = read(&get_nonce_key(address));
+            let nonces = [
+                ("nonce", account.nonce, raw_nonce.low_u128()),
+                (
+                    "deployment nonce",
+                    account.deployment_nonce,
+                    (raw_nonce >> 128).low_u128(),
+                ),
+            ];
+            for (name, expected, actual) in nonces {
+                if let Some(expected) = expected.filter(|&expected| expected as u128 != actual) {
+                    errors.push(format!(
+                        "{} of {:?} is {}, expected {}",
+                        name, address, actual, expected
+                    ));
+                }
+            }
+        }
+        if let Some(bytecode) = &account.bytecode {
+            let expected = h256_to_u256(BytecodeHash::for_bytecode(bytecode).value());
+            let actual = read(&get_code_key(address));
+            if actual != expected {
+                errors.push(format!(
+                    "bytecode hash of {:?} is {:#x}, expected {:#x}",
+                    address, actual, expected
+                ));
+            }
+        }
+        for (key, value) in &account.storage {
+            let actual = read(&StorageKey::new(
+                AccountTreeId::new(*address),
+                u256_to_h256(*key),
+            ));
+            if actual != *value {
+                errors.push(format!(
+                    "storage slot {:#x} of {:?} is {:#x}, expected {:#x}",
+                    key, address, actual, value
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// What the bootloader reported about a transaction that has ended.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EndedTx {
+    /// Result of the execution, unset if the bootloader didn't report it.
+    pub success: Option<bool>,
+    pub refund: Option<U256>,
+    /// Values of the expected state that differ, right after the transaction.
+    pub state_errors: Vec<String>,
+}
+
+impl EndedTx {
+    pub fn status(&self) -> TxStatus {
+        match self.success {
+            Some(true) => TxStatus::Success,
+            Some(false) => TxStatus::Failure,
+            None => TxStatus::Rejected,
+        }
+    }
+}
+
+/// Checks the transaction against its expectation. Transactions that haven't ended were rejected.
+pub fn check_tx(expected: &TxExpectation, ended: Option<&EndedTx>) -> Result<(), String> {
+    let status = ended.map_or(TxStatus::Rejected, EndedTx::status);
+    if status != expected.status {
+        return Err(format!("status {}, expected {}", status, expected.status));
+    }
+    let Some(ended) = ended else {
+        return Ok(());
+    };
+    let mut errors = vec![];
+    if let Some(refund) = expected.refund {
+        match ended.refund {
+            Some(actual) if actual == refund => {}
+            Some(actual) => errors.push(format!("refund {}, expected {}", actual, refund)),
+            None => errors.push(format!("no refund reported, expected {}", refund)),
+        }
+    }
+    errors.extend(ended.state_errors.iter().cloned());
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Result of a single transaction of the scenario.
+#[derive(Debug)]
+pub struct TxOutcome {
+    /// Number of the block in the scenario, starting from 1.
+    pub block: usize,
+    pub name: String,
+    pub status: TxStatus,
+    pub refund: Option<U256>,
+    pub result: Result<(), String>,
+}
+
+/// Result of a scenario: the outcome of each transaction, and the result of the whole batch.
+#[derive(Debug)]
+pub struct ScenarioOutcome {
+    pub transactions: Vec<TxOutcome>,
+    pub batch_result: ExecutionResult,
+}
+
+impl ScenarioOutcome {
+    pub fn passed(&self) -> bool {
+        self.transactions.iter().all(|tx| tx.result.is_ok())
+    }
+}
+
+/// Tracer that collects what the `proved_batch` bootloader reports about each transaction via its VM hooks,
+/// and checks the expected state when the transaction ends.
+pub struct ScenarioTracer {
+    /// Expected state after each transaction, in order.
+    expected_states: Vec<StateFixture>,
+    current: EndedTx,
+    ended: Arc<Mutex<Vec<EndedTx>>>,
+}
+
+impl ScenarioTracer {
+    pub fn new(expected_states: Vec<StateFixture>, ended: Arc<Mutex<Vec<EndedTx>>>) -> Self {
+        Self {
+            expected_states,
+            current: EndedTx::default(),
+            ended,
+        }
+    }
+}
+
+impl<S: ReadStorage, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for ScenarioTracer {
+    fn before_execution(
+        &mut self,
+        state: VmLocalStateData<'_>,
+        data: BeforeExecutionData,
+        memory: &SimpleMemory<H>,
+        storage: StoragePtr<S>,
+    ) {
+        let TestVmHook::Bootloader(hook) = TestVmHook::from_opcode_memory(&state, &data, memory)
+        else {
+            return;
+        };
+        match hook {
+            BootloaderHook::ExecutionResult(success) => self.current.success = Some(success),
+            BootloaderHook::NotifyAboutRefund(refund) => self.current.refund = Some(refund),
+            BootloaderHook::TxHasEnded => {
+                let mut ended = self.ended.lock().unwrap();
+                if let Some(expected) = self.expected_states.get(ended.len()) {
+                    self.current.state_errors = check_state(expected, |key| {
+                        h256_to_u256(storage.borrow_mut().read_value(key))
+                    });
+                }
+                ended.push(mem::take(&mut self.current));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<S: WriteStorage, H: HistoryMode> VmTracer<S, H> for ScenarioTracer {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use zksync_types::Address;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_scenario_file() {
+        let toml = r#"
+            state_fixture = "funded_account"
+            env = { batch_timestamp = 1000 }
+
+            [[blocks]]
+            [[blocks.transactions]]
+            file = "transfer.json"
+            refund = "0x10"
+
+            [[blocks]]
+            timestamp = 1010
+            [[blocks.transactions]]
+            file = "reverting.json"
+            status = "failure"
+            state = { accounts = { "0x0000000000000000000000000000000000012345" = { nonce = 2 } } }
+        "#;
+        let file = parse_scenario_file(Path::new("scenario.toml"), toml).unwrap();
+        assert_eq!(file.state_fixture.as_deref(), Some("funded_account"));
+        assert_eq!(file.env.batch_timestamp, Some(1000));
+        assert_eq!(file.blocks.len(), 2);
+        assert_eq!(file.blocks[0].transactions[0].status, TxStatus::Success);
+        assert_eq!(file.blocks[0].transactions[0].refund, Some(U256::from(16)));
+        assert_eq!(file.blocks[1].timestamp, Some(1010));
+        let tx = &file.blocks[1].transactions[0];
+        assert_eq!(tx.status, TxStatus::Failure);
+        assert_eq!(
+            tx.state.accounts[&Address::from_low_u64_be(0x12345)].nonce,
+            Some(2)
+        );
+
+        let json =
+            r#"{ "blocks": [{ "transactions": [{ "file": "0.json", "status": "rejected" }] }] }"#;
+        let file = parse_scenario_file(Path::new("scenario.json"), json).unwrap();
+        assert_eq!(file.blocks[0].transactions[0].status, TxStatus::Rejected);
+
+        let err =
+            parse_scenario_file(Path::new("scenario.json"), r#"{ "blocks": [] }"#).unwrap_err();
+        assert!(err.contains("has no blocks"), "{}", err);
+        let empty_block = r#"{ "blocks": [{ "transactions": [] }] }"#;
+        let err = parse_scenario_file(Path::new("scenario.json"), empty_block).unwrap_err();
+        assert!(err.contains("Block 1"), "{}", err);
+        let unknown_status =
+            r#"{ "blocks": [{ "transactions": [{ "file": "0.json", "status": "ok" }] }] }"#;
+        let err = parse_scenario_file(Path::new("scenario.json"), unknown_status).unwrap_err();
+        assert!(err.contains("unknown variant"), "{}", err);
+    }
+
+    #[test]
+    fn test_default_scenarios() {
+        use zksync_types::l2::TransactionType;
+
+        let scenarios = load_scenarios(&default_scenarios_dir()).unwrap();
+        let scenario = &scenarios["mixed_batch"];
+        assert_eq!(scenario.blocks.len(), 2);
+        let tx_types: Vec<_> = scenario
+            .transactions()
+            .map(|tx| tx.transaction.tx_format())
+            .collect();
+        assert_eq!(
+            tx_types,
+            vec![
+                TransactionType::ProtocolUpgradeTransaction,
+                TransactionType::PriorityOpTransaction,
+                TransactionType::EIP712Transaction
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_state() {
+        let address = Address::from_low_u64_be(0x12345);
+        let json = r#"{ "accounts": { "0x0000000000000000000000000000000000012345": {
+            "balance": "0x64", "nonce": 1, "deployment_nonce": 2, "storage": { "0x1": "0x2a" }
+        } } }"#;
+        let expected: StateFixture = serde_json::from_str(json).unwrap();
+
+        let mut slots = HashMap::new();
+        slots.insert(storage_key_for_eth_balance(&address), U256::from(100));
+        slots.insert(get_nonce_key(&address), (U256::from(2) << 128) + 1);
+        let storage_key = StorageKey::new(AccountTreeId::new(address), u256_to_h256(U256::one()));
+        slots.insert(storage_key, U256::from(42));
+        let read = |slots: &HashMap<StorageKey, U256>, key: &StorageKey| {
+            slots.get(key).copied().unwrap_or_default()
+        };
+        assert!(check_state(&expected, |key| read(&slots, key)).is_empty());
+
+        slots.insert(get_nonce_key(&address), U256::from(1));
+        slots.remove(&storage_key);
+        let errors = check_state(&expected, |key| read(&slots, key));
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(
+            errors[0].starts_with("deployment nonce of"),
+            "{}",
+            errors[0]
+        );
+        assert!(
+            errors[1].ends_with("is 0x0, expected 0x2a"),
+            "{}",
+            errors[1]
+        );
+    }
+
+    #[test]
+    fn test_check_tx() {
+        let expected = TxExpectation {
+            refund: Some(U256::from(5)),
+            ..TxExpectation::default()
+        };
+        let ended = EndedTx {
+            success: Some(true),
+            refund: Some(U256::from(5)),
+            state_errors: vec![],
+        };
+        assert!(check_tx(&expected, Some(&ended)).is_ok());
+
+        let err = check_tx(&expected, None).unwrap_err();
+        assert_eq!(err, "status rejected, expected success");
+        let failed = EndedTx {
+            success: Some(false),
+            ..ended.clone()
+        };
+        let err = check_tx(&expected, Some(&failed)).unwrap_err();
+        assert_eq!(err, "status failure, expected success");
+
+        let wrong = EndedTx {
+            refund: Some(U256::from(7)),
+            state_errors: vec!["balance of 0x.. is 0x0, expected 0x1".into()],
+            ..ended
+        };
+        let err = check_tx(&expected, Some(&wrong)).unwrap_err();
+        assert_eq!(
+            err,
+            "refund 7, expected 5; balance of 0x.. is 0x0, expected 0x1"
+        );
+
+        let rejected = TxExpectation {
+            status: TxStatus::Rejected,
+            ..TxExpectation::default()
+        };
+        assert!(check_tx(&rejected, None).is_ok());
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Deserializer};
+use zksync_state::interface::InMemoryStorage;
+use zksync_types::{
+    bytecode::BytecodeHash, get_code_key, get_known_code_key, get_nonce_key, u256_to_h256,
+    utils::storage_key_for_eth_balance, AccountTreeId, Address, StorageKey, H256, U256,
+};
+
+/// Initial state that is applied on top of the default storage (with the system contracts),
+/// for the tests that request it via `testing_useState`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateFixture {
+    #[serde(default)]
+    pub accounts: BTreeMap<Address, AccountState>,
+}
+
+/// State of a single account. Numbers are hex encoded (e.g. `"0xde0b6b3a7640000"`).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountState {
+    /// Base token balance.
+    pub balance: Option<U256>,
+    /// Transaction nonce (the minimal nonce in the `NonceHolder`).
+    pub nonce: Option<u64>,
+    pub deployment_nonce: Option<u64>,
+    /// EraVM bytecode, deployed at the address.
+    #[serde(deserialize_with = "deserialize_bytecode")]
+    pub bytecode: Option<Vec<u8>>,
+    /// Storage slots of the account.
+    pub storage: BTreeMap<U256, U256>,
+}
+
+/// State fixtures, keyed by the name of the file (without the extension).
+pub type StateFixtures = BTreeMap<String, StateFixture>;
+
+/// Directory with the state fixtures that the tests can request.
+pub fn default_state_fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/state_fixtures")
+}
+
+fn deserialize_bytecode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+    let Some(bytecode) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let bytecode = hex::decode(bytecode.trim_start_matches("0x"))
+        .map_err(|err| serde::de::Error::custom(format!("invalid bytecode hex: {}", err)))?;
+    // EraVM bytecode consists of an odd number of 32-byte words.
+    if bytecode.len() % 32 != 0 || (bytecode.len() / 32) % 2 == 0 {
+        return Err(serde::de::Error::custom(format!(
+            "bytecode length must be an odd number of 32-byte words, got {} bytes",
+            bytecode.len()
+        )));
+    }
+    Ok(Some(bytecode))
+}
+
+impl StateFixture {
+    pub fn apply(&self, storage: &mut InMemoryStorage) {
+        for (address, account) in &self.accounts {
+            if let Some(balance) = account.balance {
+                storage.set_value(storage_key_for_eth_balance(address), u256_to_h256(balance));
+            }
+            if account.nonce.is_some() || account.deployment_nonce.is_some() {
+                // The NonceHolder keeps both nonces in a single slot: deployment_nonce * 2^128 + nonce.
+                let raw_nonce = (U256::from(account.deployment_nonce.unwrap_or(0)) << 128)
+                    + U256::from(account.nonce.unwrap_or(0));
+                storage.set_value(get_nonce_key(address), u256_to_h256(raw_nonce));
+            }
+            if let Some(bytecode) = &account.bytecode {
+                let hash = BytecodeHash::for_bytecode(bytecode).value();
+                storage.set_value(get_code_key(address), hash);
+                storage.set_value(get_known_code_key(&hash), H256::from_low_u64_be(1));
+                storage.store_factory_dep(hash, bytecode.clone());
+            }
+            for (key, value) in &account.storage {
+                let key = StorageKey::new(AccountTreeId::new(*address), u256_to_h256(*key));
+                storage.set_value(key, u256_to_h256(*value));
+            }
+        }
+    }
+}
+
+fn parse_state_fixture(path: &Path, data: &str) -> Result<StateFixture, String> {
+    let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(data).map_err(|err| err.to_string()),
+        _ => serde_json::from_str(data).map_err(|err| err.to_string()),
+    };
+    parsed.map_err(|err| format!("Failed to parse state fixture {:?}: {}", path, err))
+}
+
+/// Loads all the `*.json` and `*.toml` state fixtures from the directory.
+/// Returns no fixtures if the directory doesn't exist.
+pub fn load_state_fixtures(dir: &Path) -> Result<StateFixtures, String> {
+    if !dir.exists() {
+        return Ok(StateFixtures::new());
+    }
+    let read_error =
+        |err: std::io::Error| format!("Failed to read state fixtures from {:?}: {}", dir, err);
+
+    let mut fixtures = StateFixtures::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if !matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("json" | "toml")
+        ) {
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid state fixture file name {:?}", path))?
+            .to_string();
+        let data = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+        let fixture = parse_state_fixture(&path, &data)?;
+        if fixtures.insert(name.clone(), fixture).is_some() {
+            return Err(format!(
+                "Duplicate state fixture `{}` in {:?}, there are both .json and .toml files",
+                name, dir
+            ));
+        }
+    }
+    Ok(fixtures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_state_fixture() {
+        let json = r#"{
+            "accounts": {
+                "0x0000000000000000000000000000000000012345": {
+                    "balance": "0xde0b6b3a7640000",
+                    "nonce": 3,
+                    "storage": { "0x1": "0x2a" }
+                }
+            }
+        }"#;
+        let toml = r#"
+            [accounts.0x0000000000000000000000000000000000012345]
+            balance = "0xde0b6b3a7640000"
+            nonce = 3
+            storage = { "0x1" = "0x2a" }
+        "#;
+        let from_json = parse_state_fixture(Path::new("funded.json"), json).unwrap();
+        let from_toml = parse_state_fixture(Path::new("funded.toml"), toml).unwrap();
+        assert_eq!(from_json, from_toml);
+
+        let account = &from_json.accounts[&Address::from_low_u64_be(0x12345)];
+        assert_eq!(account.balance, Some(U256::exp10(18)));
+        assert_eq!(account.nonce, Some(3));
+        assert_eq!(account.storage[&U256::one()], U256::from(42));
+
+        let err = parse_state_fixture(Path::new("bad.json"), r#"{ "acounts": {} }"#).unwrap_err();
+        assert!(err.contains("unknown field"), "{}", err);
+
+        let bad_bytecode = r#"{ "accounts": { "0x0000000000000000000000000000000000012345": { "bytecode": "0x00" } } }"#;
+        let err = parse_state_fixture(Path::new("bad.json"), bad_bytecode).unwrap_err();
+        assert!(err.contains("odd number of 32-byte words"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_default_state_fixtures() {
+        let fixtures = load_state_fixtures(&default_state_fixtures_dir()).unwrap();
+        assert!(fixtures.contains_key("funded_account"));
+    }
+}
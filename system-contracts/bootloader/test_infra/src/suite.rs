@@ -0,0 +1,240 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+use zksync_contracts::{
+    BaseSystemContracts, ContractLanguage, SystemContractCode, SystemContractsRepo,
+};
+use zksync_multivm::interface::{L1BatchEnv, L2BlockEnv, SystemEnv, TxExecutionMode};
+use zksync_state::interface::{InMemoryStorage, IN_MEMORY_STORAGE_DEFAULT_NETWORK_ID};
+use zksync_types::bytecode::BytecodeHash;
+use zksync_types::fee_model::BatchFeeInput;
+use zksync_types::system_contracts::get_system_smart_contracts_from_dir;
+use zksync_types::{block::L2BlockHasher, Address, L1BatchNumber, L2BlockNumber, L2ChainId};
+
+use crate::{
+    bootloader_hook::HookVerbosity,
+    coverage::CoverageSources,
+    debug_info::FunctionMap,
+    env_config::{self, EnvArgs},
+    fixtures,
+    fuzz::{self, FuzzConfig},
+    limits::{self, TestLimits},
+    output_snapshot::OutputSnapshots,
+    runner::TestContext,
+    state_fixtures,
+};
+
+/// Chain id of the test runs, unless it is overridden by the environment.
+pub const DEFAULT_CHAIN_ID: u32 = 299;
+
+/// Where the tests load their fixtures and environment from, and how they are run.
+/// The defaults are the same as the ones of the command line.
+#[derive(Debug, Clone)]
+pub struct SuiteOptions {
+    /// Defaults to `src/test_transactions`.
+    pub tx_fixtures: Option<PathBuf>,
+    /// Defaults to `src/state_fixtures`.
+    pub state_fixtures: Option<PathBuf>,
+    pub env: EnvArgs,
+    /// Record the executed instructions of `bootloader.yul`.
+    pub coverage: bool,
+    /// Profile the cycles and ergs spent in the bootloader functions.
+    pub profile: bool,
+    pub fuzz: FuzzConfig,
+    pub limits: TestLimits,
+    pub hook_verbosity: HookVerbosity,
+    /// Compare the logs, events and storage diffs of each test with its golden file, or rewrite the files.
+    pub output_snapshots: Option<OutputSnapshots>,
+    /// Run each passing test on the fast VM as well, and fail it if the runs diverge.
+    pub compare_fast_vm: bool,
+}
+
+impl Default for SuiteOptions {
+    fn default() -> Self {
+        Self {
+            tx_fixtures: None,
+            state_fixtures: None,
+            env: EnvArgs::default(),
+            coverage: false,
+            profile: false,
+            fuzz: FuzzConfig {
+                runs: fuzz::DEFAULT_RUNS,
+                seed: 0,
+                max_shrink_runs: fuzz::DEFAULT_MAX_SHRINK_RUNS,
+            },
+            limits: TestLimits::new(limits::DEFAULT_MAX_CYCLES, 0),
+            hook_verbosity: HookVerbosity::Off,
+            output_snapshots: None,
+            compare_fast_vm: false,
+        }
+    }
+}
+
+/// The tests of the test bootloader, ready to be discovered and run.
+pub struct TestSuite {
+    pub context: TestContext,
+    pub repo: SystemContractsRepo,
+    /// `bootloader/build`, with the preprocessed bootloaders and their compiled `artifacts`.
+    pub build_dir: PathBuf,
+    /// Sources that the coverage is mapped to, if it's recorded.
+    pub coverage_sources: Option<CoverageSources>,
+}
+
+impl TestSuite {
+    /// Loads the compiled test bootloader (`yarn build:bootloader`) and the fixtures.
+    /// Paths are relative to the current directory, which has to be `bootloader/test_infra`.
+    pub fn load(options: &SuiteOptions) -> Result<Self, String> {
+        let current_dir = env::current_dir()
+            .map_err(|err| format!("Failed to get the current directory: {}", err))?;
+        let build_dir = current_dir.join("../build");
+        let repo = SystemContractsRepo {
+            root: current_dir.join("../../"),
+        };
+
+        let bootloader = load_bootloader(&repo, &build_dir.join("artifacts"), "bootloader_test");
+
+        let bytecode =
+            repo.read_sys_contract_bytecode("", "DefaultAccount", None, ContractLanguage::Sol);
+        let hash = BytecodeHash::for_bytecode(&bytecode).value();
+        let default_aa = SystemContractCode {
+            code: bytecode,
+            hash,
+        };
+
+        let base_system_contract = BaseSystemContracts {
+            bootloader,
+            default_aa,
+            evm_emulator: None,
+        };
+
+        let mut system_env = SystemEnv {
+            zk_porter_available: false,
+            version: zksync_types::ProtocolVersionId::latest(),
+            base_system_smart_contracts: base_system_contract,
+            bootloader_gas_limit: u32::MAX,
+            execution_mode: TxExecutionMode::VerifyExecute,
+            default_validation_computational_gas_limit: u32::MAX,
+            chain_id: L2ChainId::from(DEFAULT_CHAIN_ID),
+        };
+
+        let mut l1_batch_env = L1BatchEnv {
+            previous_batch_hash: None,
+            number: L1BatchNumber::from(1),
+            timestamp: 14,
+            fee_input: BatchFeeInput::sensible_l1_pegged_default(),
+            fee_account: Address::default(),
+
+            enforced_base_fee: None,
+            first_l2_block: L2BlockEnv {
+                number: 1,
+                timestamp: 15,
+                prev_block_hash: L2BlockHasher::legacy_hash(L2BlockNumber(0)),
+                max_virtual_blocks_to_create: 1,
+                interop_roots: vec![],
+            },
+        };
+
+        // The environment can be overridden by the config file, and then by the command line.
+        let env_config_path = options
+            .env
+            .env_config
+            .clone()
+            .unwrap_or_else(env_config::default_env_config_path);
+        let env_config = env_config::load_env_config(&env_config_path)?;
+        env_config
+            .default
+            .overridden_by(&options.env.to_config())
+            .apply(&mut system_env, &mut l1_batch_env);
+
+        let tx_fixtures_dir = options
+            .tx_fixtures
+            .clone()
+            .unwrap_or_else(fixtures::default_tx_fixtures_dir);
+        let transactions = fixtures::load_transactions(&tx_fixtures_dir)?;
+        let fixture_sets = fixtures::load_fixture_sets(&tx_fixtures_dir)?;
+        let state_fixtures_dir = options
+            .state_fixtures
+            .clone()
+            .unwrap_or_else(state_fixtures::default_state_fixtures_dir);
+        let state_fixtures = state_fixtures::load_state_fixtures(&state_fixtures_dir)?;
+
+        // The executed instructions are mapped back to `bootloader.yul` via the assembly of the test bootloader,
+        // and the map of its lines written by the preprocessor.
+        let coverage_sources = if options.coverage {
+            Some(CoverageSources::load(
+                &assembly_path(&build_dir.join("artifacts"), "bootloader_test"),
+                &build_dir.join("bootloader_test.lines.json"),
+                &current_dir.join("../bootloader.yul"),
+            )?)
+        } else {
+            None
+        };
+        let profiler = if options.profile {
+            Some(Arc::new(load_function_map(&build_dir, "bootloader_test")?))
+        } else {
+            None
+        };
+
+        let context = TestContext {
+            l1_batch_env,
+            system_env,
+            storage: InMemoryStorage::with_custom_system_contracts_and_chain_id(
+                L2ChainId::from(IN_MEMORY_STORAGE_DEFAULT_NETWORK_ID),
+                get_system_smart_contracts_from_dir(current_dir.join("../../")),
+            ),
+            transactions,
+            fixture_sets,
+            state_fixtures,
+            envs: env_config.envs,
+            coverage: coverage_sources.as_ref().map(|_| Arc::default()),
+            profiler,
+            fuzz: options.fuzz,
+            limits: options.limits,
+            hook_verbosity: options.hook_verbosity,
+            output_snapshots: options.output_snapshots.clone(),
+            compare_fast_vm: options.compare_fast_vm,
+            discovered_tests: OnceCell::new(),
+        };
+
+        Ok(Self {
+            context,
+            repo,
+            build_dir,
+            coverage_sources,
+        })
+    }
+}
+
+/// Path of the assembly of the compiled bootloader, e.g. `bootloader_test`.
+pub fn assembly_path(artifacts_location: &Path, name: &str) -> PathBuf {
+    artifacts_location.join(format!("{name}.yul/{name}.yul.zasm"))
+}
+
+/// Loads the functions of the compiled bootloader, e.g. `bootloader_test`.
+pub fn load_function_map(build_dir: &Path, name: &str) -> Result<FunctionMap, String> {
+    FunctionMap::load(
+        &assembly_path(&build_dir.join("artifacts"), name),
+        &build_dir.join(format!("{name}.yul")),
+    )
+}
+
+/// Loads a compiled Yul file that can be used as the bootloader, e.g. `proved_batch`.
+pub fn load_bootloader(
+    repo: &SystemContractsRepo,
+    artifacts_location: &Path,
+    name: &str,
+) -> SystemContractCode {
+    let bytecode = repo.read_sys_contract_bytecode(
+        artifacts_location.to_str().unwrap(),
+        name,
+        Some("Bootloader"),
+        ContractLanguage::Yul,
+    );
+    let hash = BytecodeHash::for_bytecode(&bytecode).value();
+    SystemContractCode {
+        code: bytecode,
+        hash,
+    }
+}
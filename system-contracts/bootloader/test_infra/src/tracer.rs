@@ -1,6 +1,5 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use colored::Colorize;
 use once_cell::sync::OnceCell;
 
 use zksync_multivm::tracers::dynamic::vm_1_5_2::DynTracer;
@@ -11,9 +10,41 @@ use zksync_multivm::vm_latest::{
 };
 use zksync_multivm::zk_evm_latest::tracing::{BeforeExecutionData, VmLocalStateData};
 
-use zksync_state::interface::{StoragePtr, WriteStorage};
+use zksync_state::interface::{ReadStorage, StoragePtr, WriteStorage};
+use zksync_types::{h256_to_u256, AccountTreeId, Address, StorageKey, H256, U256};
 
-use crate::hook::TestVmHook;
+use crate::bootloader_hook::HookVerbosity;
+use crate::expected_failure::ExpectedFailure;
+use crate::fuzz::FuzzInputs;
+use crate::hook::{set_test_hook_result, test_hook_as_int_or_hex, TestVmHook};
+use crate::limits::TestLimits;
+use crate::memory_dump::format_memory_dump;
+
+/// Setup that the test requested via `testing_useFixture`, `testing_useState` and `testing_useEnv`.
+/// It has to be in place before the test starts, so it is collected by a discovery run of the test.
+#[derive(Debug, Clone, Default)]
+pub struct RequestedSetup {
+    pub fixture: Arc<OnceCell<String>>,
+    pub state_fixture: Arc<OnceCell<String>>,
+    pub env: Arc<OnceCell<String>>,
+}
+
+impl RequestedSetup {
+    // Returns the value requested by the setup hook, where it should be stored, and the name of the hook.
+    fn request<'a>(
+        &self,
+        hook: &'a TestVmHook,
+    ) -> Option<(&'a String, &OnceCell<String>, &'static str)> {
+        match hook {
+            TestVmHook::UseFixture(fixture) => Some((fixture, &self.fixture, "testing_useFixture")),
+            TestVmHook::UseState(state_fixture) => {
+                Some((state_fixture, &self.state_fixture, "testing_useState"))
+            }
+            TestVmHook::UseEnv(env) => Some((env, &self.env, "testing_useEnv")),
+            _ => None,
+        }
+    }
+}
 
 /// Bootloader test tracer that is executing while the bootloader tests are running.
 /// It can check the asserts, return information about the running tests (and amount of tests) etc.
@@ -21,47 +52,203 @@ pub struct BootloaderTestTracer {
     /// Set if the currently running test has failed.
     test_result: Arc<OnceCell<Result<(), String>>>,
     /// Set, if the currently running test should fail with a given assert.
-    requested_assert: Arc<OnceCell<String>>,
+    requested_assert: Arc<OnceCell<ExpectedFailure>>,
 
     test_name: Arc<OnceCell<String>>,
+    /// Lines logged by the test. They are buffered, so that the output of tests running in parallel doesn't interleave.
+    test_logs: Arc<Mutex<Vec<String>>>,
+    requested_setup: RequestedSetup,
+
+    /// Inputs of the fuzz test, with the words requested so far.
+    fuzz_inputs: Arc<Mutex<FuzzInputs>>,
+    /// Word requested via `testing_fuzzWord`, to be written into the memory at the end of the cycle.
+    pending_fuzz_word: Option<U256>,
+
+    /// Budgets of the run, which the test can change via `testing_setLimits`.
+    limits: TestLimits,
+    /// Ergs of all the frames at the start of the run. Recorded even without an ergs limit, as the test can
+    /// set one later, and the ergs spent before count towards it.
+    initial_ergs: Option<u64>,
+    /// Contract and program counter of the last executed instruction, reported if the test times out.
+    last_pc: (Address, u16),
+    /// Which of the bootloader's own hooks (e.g. `debugLog`) are added to the logs of the test.
+    hook_verbosity: HookVerbosity,
+
+    /// In discovery mode, the tracer only collects the test setup (its name and fixtures),
+    /// and stops the execution at the first hook that is not a part of the setup.
+    discovery: bool,
+    setup_finished: bool,
 }
 
 impl BootloaderTestTracer {
     pub fn new(
         test_result: Arc<OnceCell<Result<(), String>>>,
-        requested_assert: Arc<OnceCell<String>>,
+        requested_assert: Arc<OnceCell<ExpectedFailure>>,
         test_name: Arc<OnceCell<String>>,
+        test_logs: Arc<Mutex<Vec<String>>>,
+        requested_setup: RequestedSetup,
     ) -> Self {
         BootloaderTestTracer {
             test_result,
             requested_assert,
             test_name,
+            test_logs,
+            requested_setup,
+            fuzz_inputs: Arc::default(),
+            pending_fuzz_word: None,
+            limits: TestLimits::default(),
+            initial_ergs: None,
+            last_pc: (Address::zero(), 0),
+            hook_verbosity: HookVerbosity::default(),
+            discovery: false,
+            setup_finished: false,
+        }
+    }
+
+    /// Switches the tracer to the discovery mode, which is used to learn which transactions,
+    /// initial state and environment have to be set up before the test is executed.
+    pub fn discovery_mode(mut self) -> Self {
+        self.discovery = true;
+        self
+    }
+
+    /// Sets the inputs that `testing_fuzzWord` returns in this run.
+    pub fn with_fuzz_inputs(mut self, fuzz_inputs: Arc<Mutex<FuzzInputs>>) -> Self {
+        self.fuzz_inputs = fuzz_inputs;
+        self
+    }
+
+    /// Sets the budgets of the run.
+    pub fn with_limits(mut self, limits: TestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets which of the bootloader's own hooks are logged.
+    pub fn with_hook_verbosity(mut self, hook_verbosity: HookVerbosity) -> Self {
+        self.hook_verbosity = hook_verbosity;
+        self
+    }
+
+    pub(crate) fn handle_discovery_hook(&mut self, hook: &TestVmHook) {
+        // The run is stopped at the end of the cycle, later hooks are not a part of the setup.
+        if self.setup_finished {
+            return;
+        }
+        if let Some((value, cell, _)) = self.requested_setup.request(hook) {
+            let _ = cell.set(value.clone());
+            return;
+        }
+        match hook {
+            TestVmHook::NoHook | TestVmHook::RequestedAssert(_) | TestVmHook::Bootloader(_) => {}
+            TestVmHook::TestStart(test_name) => {
+                let _ = self.test_name.set(test_name.clone());
+            }
+            _ => self.setup_finished = true,
         }
     }
 }
 
-impl<S, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for BootloaderTestTracer {
+// Reads the current value of the slot, including the writes made earlier in this run.
+fn read_storage_slot<S: ReadStorage>(storage: &StoragePtr<S>, address: Address, key: H256) -> U256 {
+    let key = StorageKey::new(AccountTreeId::new(address), key);
+    h256_to_u256(storage.borrow_mut().read_value(&key))
+}
+
+impl<S: ReadStorage, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for BootloaderTestTracer {
     fn before_execution(
         &mut self,
         state: VmLocalStateData<'_>,
         data: BeforeExecutionData,
         memory: &SimpleMemory<H>,
-        _storage: StoragePtr<S>,
+        storage: StoragePtr<S>,
     ) {
+        let frame = &state.vm_local_state.callstack.current;
+        self.last_pc = (frame.code_address, frame.pc);
+
         let hook = TestVmHook::from_opcode_memory(&state, &data, memory);
 
-        if let TestVmHook::TestLog(msg, data_str) = &hook {
-            println!("{} {} {}", "Test log".bold(), msg, data_str);
+        if self.discovery {
+            self.handle_discovery_hook(&hook);
+            return;
         }
-        if let TestVmHook::AssertEqFailed(a, b, msg) = &hook {
-            let result = format!("Assert failed: {} is not equal to {}: {}", a, b, msg);
 
-            self.test_result.set(Err(result.clone())).unwrap();
+        if let TestVmHook::TestLog(msg, data_str) = &hook {
+            self.test_logs
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", msg, data_str));
+        }
+        if let TestVmHook::TestLogValues(msg, values) = &hook {
+            self.test_logs
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", msg, values.join(", ")));
+        }
+        if let TestVmHook::Bootloader(bootloader_hook) = &hook {
+            if bootloader_hook.is_logged(self.hook_verbosity) {
+                self.test_logs
+                    .lock()
+                    .unwrap()
+                    .push(format!("bootloader: {}", bootloader_hook));
+            }
+        }
+        if let TestVmHook::DumpMemory(offset, words, regions) = &hook {
+            self.test_logs
+                .lock()
+                .unwrap()
+                .extend(format_memory_dump(*offset, words, regions));
+        }
+        if let TestVmHook::AssertStorage(address, key, expected, msg) = &hook {
+            let actual = read_storage_slot(&storage, *address, *key);
+            if actual != *expected {
+                let _ = self.test_result.set(Err(format!(
+                    "Assert failed: storage slot {} of {:?} is {}, expected {}: {}",
+                    test_hook_as_int_or_hex(h256_to_u256(*key)),
+                    address,
+                    test_hook_as_int_or_hex(actual),
+                    test_hook_as_int_or_hex(*expected),
+                    msg
+                )));
+            }
+        }
+        if let TestVmHook::LogStorage(msg, address, key) = &hook {
+            let value = read_storage_slot(&storage, *address, *key);
+            self.test_logs.lock().unwrap().push(format!(
+                "{} {:?}[{}] = {}",
+                msg,
+                address,
+                test_hook_as_int_or_hex(h256_to_u256(*key)),
+                test_hook_as_int_or_hex(value)
+            ));
+        }
+        if let TestVmHook::SetLimits(max_cycles, max_ergs) = &hook {
+            self.limits = self.limits.overridden_by(*max_cycles, *max_ergs);
+        }
+        if let TestVmHook::FuzzWord(index) = &hook {
+            self.pending_fuzz_word = Some(self.fuzz_inputs.lock().unwrap().word(*index));
+        }
+        if let Some(failure) = hook.assert_failure() {
+            let _ = self.test_result.set(Err(failure));
+        }
+        if let TestVmHook::InvalidParams(hook_name, err) = &hook {
+            let _ = self
+                .test_result
+                .set(Err(format!("Invalid params of {}: {}", hook_name, err)));
         }
         if let TestVmHook::RequestedAssert(requested_assert) = &hook {
             let _ = self.requested_assert.set(requested_assert.clone());
         }
 
+        if let Some((value, cell, hook_name)) = self.requested_setup.request(&hook) {
+            if cell.set(value.clone()).is_err() {
+                let _ = self.test_result.set(Err(format!(
+                    "{} can only be called once per test",
+                    hook_name
+                )));
+            }
+        }
+
         if let TestVmHook::TestStart(test_name) = &hook {
             self.test_name
                 .set(test_name.clone())
@@ -73,9 +260,44 @@ impl<S, H: HistoryMode> DynTracer<S, SimpleMemory<H>> for BootloaderTestTracer {
 impl<S: WriteStorage, H: HistoryMode> VmTracer<S, H> for BootloaderTestTracer {
     fn finish_cycle(
         &mut self,
-        _state: &mut ZkSyncVmState<S, H>,
+        state: &mut ZkSyncVmState<S, H>,
         _bootloader_state: &mut BootloaderState,
     ) -> TracerExecutionStatus {
+        // The hook is triggered before the write of its id, so the word can only be written once it is done.
+        if let Some(word) = self.pending_fuzz_word.take() {
+            set_test_hook_result(&mut state.memory, state.local_state.timestamp, word);
+        }
+        if self.discovery && self.setup_finished {
+            return TracerExecutionStatus::Stop(TracerExecutionStopReason::Finish);
+        }
+
+        // The ergs passed to a call are taken from the caller's frame, so the ergs of all the frames
+        // only decrease by the ergs that were spent.
+        let callstack = &state.local_state.callstack;
+        let ergs_remaining = callstack
+            .inner
+            .iter()
+            .chain([&callstack.current])
+            .map(|frame| frame.ergs_remaining as u64)
+            .sum();
+        let initial_ergs = *self.initial_ergs.get_or_insert(ergs_remaining);
+        let ergs_used = initial_ergs.saturating_sub(ergs_remaining);
+        if let Some(exceeded) = self
+            .limits
+            .exceeded(state.local_state.monotonic_cycle_counter, ergs_used)
+        {
+            let (address, pc) = self.last_pc;
+            let result = match self.requested_assert.get() {
+                Some(expected @ ExpectedFailure::Timeout(_)) => expected.check_timeout(&exceeded),
+                _ => Err(format!(
+                    "Timed out: {}, last executed pc {} of {:?}",
+                    exceeded, pc, address
+                )),
+            };
+            let _ = self.test_result.set(result);
+            // The test is stopped even if it was expected to time out.
+            return TracerExecutionStatus::Stop(TracerExecutionStopReason::Finish);
+        }
         if let Some(Err(_)) = self.test_result.get() {
             TracerExecutionStatus::Stop(TracerExecutionStopReason::Finish)
         } else {
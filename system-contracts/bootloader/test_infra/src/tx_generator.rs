@@ -0,0 +1,587 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use zksync_types::{
+    abi,
+    fee::Fee,
+    l1::{L1Tx, L1TxCommonData, OpProcessingType, PriorityQueueType},
+    l2::L2Tx,
+    protocol_upgrade::{ProtocolUpgradeTx, ProtocolUpgradeTxCommonData},
+    transaction_request::{PaymasterParams, TransactionRequest},
+    web3::Bytes,
+    Address, Execute, K256PrivateKey, L2ChainId, Nonce, PackedEthSignature, PriorityOpId,
+    ProtocolVersionId, Transaction, CONTRACT_FORCE_DEPLOYER_ADDRESS, EIP_1559_TX_TYPE, H256, U256,
+    U64,
+};
+
+use crate::suite::DEFAULT_CHAIN_ID;
+
+const DEFAULT_GAS_LIMIT: u64 = 10_000_000;
+/// Same as the fee of the default transaction fixture, and above the base fee of the default environment.
+const DEFAULT_MAX_FEE_PER_GAS: u64 = 250_000_000;
+const DEFAULT_L2_GAS_PER_PUBDATA_LIMIT: u64 = 50_000;
+/// Gas per pubdata byte that L1 and upgrade transactions are required to have.
+const DEFAULT_L1_GAS_PER_PUBDATA_LIMIT: u64 = 800;
+
+/// Private key of the test account with the given index. It's the key `index + 1`, so e.g. account 0 has the key
+/// `0x00..01` and the address `0x7e5f4552091a69125d5dfcb7b8c2659029395bdf`.
+pub fn test_key(index: u32) -> K256PrivateKey {
+    K256PrivateKey::from_bytes(H256::from_low_u64_be(u64::from(index) + 1))
+        .expect("Test keys are valid")
+}
+
+/// L2 transaction, signed by one of the test keys.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct L2TxSpec {
+    /// Index of the test key that signs the transaction (see [`test_key`]).
+    pub signer: u32,
+    pub nonce: u32,
+    /// Called contract. Unset for EVM contract deployments.
+    pub to: Option<Address>,
+    pub calldata: Bytes,
+    pub value: U256,
+    pub gas_limit: U256,
+    /// Gas price of legacy transactions.
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// Only encoded in EIP-712 transactions.
+    pub gas_per_pubdata_limit: U256,
+    /// Bytecodes published with the transaction. Only supported by EIP-712 transactions.
+    pub factory_deps: Vec<Bytes>,
+    /// Only supported by EIP-712 transactions.
+    pub paymaster: Option<Address>,
+    pub paymaster_input: Bytes,
+}
+
+impl Default for L2TxSpec {
+    fn default() -> Self {
+        Self {
+            signer: 0,
+            nonce: 0,
+            to: None,
+            calldata: Bytes::default(),
+            value: U256::zero(),
+            gas_limit: DEFAULT_GAS_LIMIT.into(),
+            max_fee_per_gas: DEFAULT_MAX_FEE_PER_GAS.into(),
+            max_priority_fee_per_gas: U256::zero(),
+            gas_per_pubdata_limit: DEFAULT_L2_GAS_PER_PUBDATA_LIMIT.into(),
+            factory_deps: vec![],
+            paymaster: None,
+            paymaster_input: Bytes::default(),
+        }
+    }
+}
+
+/// L1 priority or protocol upgrade transaction. They are not signed.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct L1TxSpec {
+    /// Defaults to the address of the test account 0 for priority transactions, and to the force deployer for upgrades.
+    pub sender: Option<Address>,
+    pub to: Option<Address>,
+    pub calldata: Bytes,
+    pub value: U256,
+    pub gas_limit: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_per_pubdata_limit: U256,
+    /// Base token minted to the sender. Defaults to `gas_limit * max_fee_per_gas + value`, which covers the transaction.
+    pub to_mint: Option<U256>,
+    /// Defaults to the sender.
+    pub refund_recipient: Option<Address>,
+    /// Id in the priority queue, only for priority transactions. Defaults to the one after the previous priority
+    /// transaction of the file (or 0).
+    pub serial_id: Option<u64>,
+    pub factory_deps: Vec<Bytes>,
+}
+
+impl Default for L1TxSpec {
+    fn default() -> Self {
+        Self {
+            sender: None,
+            to: None,
+            calldata: Bytes::default(),
+            value: U256::zero(),
+            gas_limit: DEFAULT_GAS_LIMIT.into(),
+            max_fee_per_gas: DEFAULT_MAX_FEE_PER_GAS.into(),
+            gas_per_pubdata_limit: DEFAULT_L1_GAS_PER_PUBDATA_LIMIT.into(),
+            to_mint: None,
+            refund_recipient: None,
+            serial_id: None,
+            factory_deps: vec![],
+        }
+    }
+}
+
+impl L1TxSpec {
+    fn execute(&self) -> Execute {
+        Execute {
+            contract_address: self.to,
+            calldata: self.calldata.0.clone(),
+            value: self.value,
+            factory_deps: self.factory_deps.iter().map(|dep| dep.0.clone()).collect(),
+        }
+    }
+
+    fn to_mint(&self) -> Result<U256, String> {
+        if let Some(to_mint) = self.to_mint {
+            return Ok(to_mint);
+        }
+        self.gas_limit
+            .checked_mul(self.max_fee_per_gas)
+            .and_then(|fee| fee.checked_add(self.value))
+            .ok_or_else(|| {
+                "`gas_limit * max_fee_per_gas + value` overflows, set `to_mint` explicitly"
+                    .to_string()
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxSpec {
+    Eip712(L2TxSpec),
+    Legacy(L2TxSpec),
+    Eip1559(L2TxSpec),
+    /// L1 priority transaction.
+    L1(L1TxSpec),
+    Upgrade(L1TxSpec),
+}
+
+/// Transactions to generate, in the order of the written files.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TxSpecFile {
+    /// Chain id that the L2 transactions are signed for. Defaults to the one of the test runs.
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u32,
+    pub transactions: Vec<TxSpec>,
+}
+
+fn default_chain_id() -> u32 {
+    DEFAULT_CHAIN_ID
+}
+
+fn parse_tx_spec(path: &Path, data: &str) -> Result<TxSpecFile, String> {
+    let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(data).map_err(|err| err.to_string()),
+        _ => toml::from_str(data).map_err(|err| err.to_string()),
+    };
+    parsed.map_err(|err| format!("Failed to parse transaction spec {:?}: {}", path, err))
+}
+
+/// Loads the TOML (or JSON) file with the transactions to generate.
+pub fn load_tx_spec(path: &Path) -> Result<TxSpecFile, String> {
+    let data =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {:?}: {}", path, err))?;
+    parse_tx_spec(path, &data)
+}
+
+fn eip712_tx(spec: &L2TxSpec, chain_id: L2ChainId) -> Result<Transaction, String> {
+    let paymaster_params = PaymasterParams {
+        paymaster: spec.paymaster.unwrap_or_default(),
+        paymaster_input: spec.paymaster_input.0.clone(),
+    };
+    let tx = L2Tx::new_signed(
+        spec.to,
+        spec.calldata.0.clone(),
+        Nonce(spec.nonce),
+        Fee {
+            gas_limit: spec.gas_limit,
+            max_fee_per_gas: spec.max_fee_per_gas,
+            max_priority_fee_per_gas: spec.max_priority_fee_per_gas,
+            gas_per_pubdata_limit: spec.gas_per_pubdata_limit,
+        },
+        spec.value,
+        chain_id,
+        &test_key(spec.signer),
+        spec.factory_deps.iter().map(|dep| dep.0.clone()).collect(),
+        paymaster_params,
+    )
+    .map_err(|err| format!("Failed to sign: {}", err))?;
+    Ok(tx.into())
+}
+
+// Legacy (`transaction_type` unset) or EIP-1559 transaction, signed and RLP-encoded like the ones sent via the API.
+fn signed_request_tx(
+    spec: &L2TxSpec,
+    transaction_type: Option<u8>,
+    chain_id: L2ChainId,
+) -> Result<Transaction, String> {
+    if !spec.factory_deps.is_empty() || spec.paymaster.is_some() {
+        return Err(
+            "Factory deps and paymasters are only supported by EIP-712 transactions".into(),
+        );
+    }
+    let key = test_key(spec.signer);
+    let request = TransactionRequest {
+        nonce: spec.nonce.into(),
+        from: Some(key.address()),
+        to: spec.to,
+        value: spec.value,
+        gas_price: spec.max_fee_per_gas,
+        gas: spec.gas_limit,
+        max_priority_fee_per_gas: transaction_type.map(|_| spec.max_priority_fee_per_gas),
+        input: spec.calldata.clone(),
+        transaction_type: transaction_type.map(U64::from),
+        chain_id: Some(chain_id.as_u64()),
+        ..TransactionRequest::default()
+    };
+    let message = request
+        .get_default_signed_message()
+        .map_err(|err| format!("Failed to encode: {}", err))?;
+    let signature = PackedEthSignature::sign_raw(&key, &message)
+        .map_err(|err| format!("Failed to sign: {}", err))?;
+    let raw = request
+        .get_signed_bytes(&signature)
+        .map_err(|err| format!("Failed to encode: {}", err))?;
+    // Decoding the signed bytes back gives the request (and the hash) that the node would see.
+    let (request, hash) = TransactionRequest::from_bytes(&raw, chain_id)
+        .map_err(|err| format!("Failed to decode: {}", err))?;
+    let mut tx = L2Tx::from_request(request, usize::MAX, true)
+        .map_err(|err| format!("Failed to decode: {}", err))?;
+    tx.set_input(raw, hash);
+    Ok(tx.into())
+}
+
+// Hash of the ABI-encoded `L2CanonicalTransaction`, which the bootloader computes for L1 and upgrade transactions,
+// e.g. for the hash of their L2 block.
+fn canonical_tx_hash(tx: Transaction) -> Result<H256, String> {
+    match abi::Transaction::try_from(tx).map_err(|err| err.to_string())? {
+        abi::Transaction::L1 { tx, .. } => Ok(tx.hash()),
+        _ => unreachable!("L1 and upgrade transactions are encoded as L1 ones"),
+    }
+}
+
+fn l1_tx(spec: &L1TxSpec, serial_id: u64) -> Result<Transaction, String> {
+    let sender = spec.sender.unwrap_or_else(|| test_key(0).address());
+    let mut tx = L1Tx {
+        execute: spec.execute(),
+        common_data: L1TxCommonData {
+            sender,
+            serial_id: PriorityOpId(serial_id),
+            layer_2_tip_fee: U256::zero(),
+            full_fee: U256::zero(),
+            max_fee_per_gas: spec.max_fee_per_gas,
+            gas_limit: spec.gas_limit,
+            gas_per_pubdata_limit: spec.gas_per_pubdata_limit,
+            op_processing_type: OpProcessingType::Common,
+            priority_queue_type: PriorityQueueType::Deque,
+            canonical_tx_hash: H256::zero(),
+            to_mint: spec.to_mint()?,
+            refund_recipient: spec.refund_recipient.unwrap_or(sender),
+            eth_block: 0,
+        },
+        received_timestamp_ms: 0,
+    };
+    tx.common_data.canonical_tx_hash = canonical_tx_hash(tx.clone().into())?;
+    Ok(tx.into())
+}
+
+fn upgrade_tx(spec: &L1TxSpec) -> Result<Transaction, String> {
+    if spec.serial_id.is_some() {
+        return Err("Upgrade transactions don't have a serial id".into());
+    }
+    let sender = spec.sender.unwrap_or(CONTRACT_FORCE_DEPLOYER_ADDRESS);
+    let mut tx = ProtocolUpgradeTx {
+        execute: spec.execute(),
+        common_data: ProtocolUpgradeTxCommonData {
+            sender,
+            upgrade_id: ProtocolVersionId::latest(),
+            max_fee_per_gas: spec.max_fee_per_gas,
+            gas_limit: spec.gas_limit,
+            gas_per_pubdata_limit: spec.gas_per_pubdata_limit,
+            eth_block: 0,
+            canonical_tx_hash: H256::zero(),
+            to_mint: spec.to_mint()?,
+            refund_recipient: spec.refund_recipient.unwrap_or(sender),
+        },
+        received_timestamp_ms: 0,
+    };
+    tx.common_data.canonical_tx_hash = canonical_tx_hash(tx.clone().into())?;
+    Ok(tx.into())
+}
+
+/// Builds the transactions of the spec, in order.
+pub fn generate_transactions(spec: &TxSpecFile) -> Result<Vec<Transaction>, String> {
+    let chain_id = L2ChainId::from(spec.chain_id);
+    // `None` once the previous serial id is the largest one.
+    let mut next_serial_id = Some(0);
+    spec.transactions
+        .iter()
+        .enumerate()
+        .map(|(index, tx)| {
+            let generated = match tx {
+                TxSpec::Eip712(spec) => eip712_tx(spec, chain_id),
+                TxSpec::Legacy(spec) => signed_request_tx(spec, None, chain_id),
+                TxSpec::Eip1559(spec) => signed_request_tx(spec, Some(EIP_1559_TX_TYPE), chain_id),
+                TxSpec::L1(spec) => spec
+                    .serial_id
+                    .or(next_serial_id)
+                    .ok_or_else(|| {
+                        "`serial_id` of the previous L1 transaction is the largest one".to_string()
+                    })
+                    .and_then(|serial_id| {
+                        next_serial_id = serial_id.checked_add(1);
+                        l1_tx(spec, serial_id)
+                    }),
+                TxSpec::Upgrade(spec) => upgrade_tx(spec),
+            };
+            generated.map_err(|err| format!("Transaction {}: {}", index, err))
+        })
+        .collect()
+}
+
+/// Writes the transactions as `<start>.json`, `<start + 1>.json`, ... into the directory, and returns the paths.
+pub fn write_transactions(
+    dir: &Path,
+    start: usize,
+    transactions: &[Transaction],
+) -> Result<Vec<PathBuf>, String> {
+    fs::create_dir_all(dir).map_err(|err| format!("Failed to create {:?}: {}", dir, err))?;
+    (start..)
+        .zip(transactions)
+        .map(|(index, tx)| {
+            let path = dir.join(format!("{}.json", index));
+            let data = serde_json::to_string_pretty(tx).expect("Failed to serialize transaction");
+            fs::write(&path, data + "\n")
+                .map_err(|err| format!("Failed to write {:?}: {}", path, err))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use zksync_types::{
+        ethabi::{self, Token},
+        web3::keccak256,
+        ExecuteTransactionCommon,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_tx_spec() {
+        let toml = r#"
+            [[transactions]]
+            type = "eip712"
+            signer = 1
+            nonce = 2
+            to = "0x0000000000000000000000000000000000012345"
+            calldata = "0xa4136862"
+            paymaster = "0x0000000000000000000000000000000000054321"
+
+            [[transactions]]
+            type = "l1"
+            value = "0x64"
+
+            [[transactions]]
+            type = "upgrade"
+        "#;
+        let spec = parse_tx_spec(Path::new("spec.toml"), toml).unwrap();
+        assert_eq!(spec.chain_id, DEFAULT_CHAIN_ID);
+        assert_eq!(spec.transactions.len(), 3);
+        let TxSpec::Eip712(eip712) = &spec.transactions[0] else {
+            panic!("unexpected {:?}", spec.transactions[0]);
+        };
+        assert_eq!(eip712.signer, 1);
+        assert_eq!(eip712.nonce, 2);
+        assert_eq!(eip712.calldata.0, vec![0xa4, 0x13, 0x68, 0x62]);
+        assert_eq!(eip712.gas_limit, U256::from(DEFAULT_GAS_LIMIT));
+        assert_eq!(eip712.paymaster, Some(Address::from_low_u64_be(0x54321)));
+        let TxSpec::L1(l1) = &spec.transactions[1] else {
+            panic!("unexpected {:?}", spec.transactions[1]);
+        };
+        assert_eq!(
+            l1.to_mint(),
+            Ok(U256::from(DEFAULT_GAS_LIMIT) * DEFAULT_MAX_FEE_PER_GAS + 100)
+        );
+        assert_eq!(spec.transactions[2], TxSpec::Upgrade(L1TxSpec::default()));
+
+        let json = r#"{ "chain_id": 270, "transactions": [{ "type": "legacy", "nonce": 1 }] }"#;
+        let spec = parse_tx_spec(Path::new("spec.json"), json).unwrap();
+        assert_eq!(spec.chain_id, 270);
+        assert_eq!(
+            spec.transactions,
+            vec![TxSpec::Legacy(L2TxSpec {
+                nonce: 1,
+                ..L2TxSpec::default()
+            })]
+        );
+
+        let err = parse_tx_spec(
+            Path::new("spec.toml"),
+            "[[transactions]]\ntype = \"eip4844\"",
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown variant"), "{}", err);
+        let err = parse_tx_spec(
+            Path::new("spec.toml"),
+            "[[transactions]]\ntype = \"l1\"\nsigner = 0",
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown field"), "{}", err);
+    }
+
+    // `keccak256(abi.encode(L2CanonicalTransaction))` of an L1 or upgrade transaction, encoded by hand instead of
+    // through `abi::Transaction`.
+    fn expected_canonical_tx_hash(tx_type: u8, nonce: U256, tx: &Transaction) -> H256 {
+        let (sender, gas_limit, gas_per_pubdata_limit, max_fee_per_gas, to_mint, refund_recipient) =
+            match &tx.common_data {
+                ExecuteTransactionCommon::L1(data) => (
+                    data.sender,
+                    data.gas_limit,
+                    data.gas_per_pubdata_limit,
+                    data.max_fee_per_gas,
+                    data.to_mint,
+                    data.refund_recipient,
+                ),
+                ExecuteTransactionCommon::ProtocolUpgrade(data) => (
+                    data.sender,
+                    data.gas_limit,
+                    data.gas_per_pubdata_limit,
+                    data.max_fee_per_gas,
+                    data.to_mint,
+                    data.refund_recipient,
+                ),
+                ExecuteTransactionCommon::L2(_) => panic!("unexpected {:?}", tx),
+            };
+        let address = |address: Address| Token::Uint(U256::from_big_endian(address.as_bytes()));
+        let encoded = ethabi::encode(&[Token::Tuple(vec![
+            Token::Uint(tx_type.into()),
+            address(sender),
+            address(tx.execute.contract_address.unwrap_or_default()),
+            Token::Uint(gas_limit),
+            Token::Uint(gas_per_pubdata_limit),
+            Token::Uint(max_fee_per_gas),
+            Token::Uint(U256::zero()),
+            Token::Uint(U256::zero()),
+            Token::Uint(nonce),
+            Token::Uint(tx.execute.value),
+            Token::FixedArray(vec![
+                Token::Uint(to_mint),
+                address(refund_recipient),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+            ]),
+            Token::Bytes(tx.execute.calldata.clone()),
+            Token::Bytes(vec![]),
+            Token::Array(vec![]),
+            Token::Bytes(vec![]),
+            Token::Bytes(vec![]),
+        ])]);
+        H256(keccak256(&encoded))
+    }
+
+    #[test]
+    fn test_generate_transactions() {
+        let toml = r#"
+            [[transactions]]
+            type = "eip712"
+            signer = 0
+            to = "0x2b5ad5c4795c026514f8317c7a215e218dccd6cf"
+            calldata = "0xa4136862"
+
+            [[transactions]]
+            type = "legacy"
+            signer = 1
+            nonce = 3
+            to = "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+            value = "0x1"
+
+            [[transactions]]
+            type = "eip1559"
+            signer = 2
+            nonce = 5
+            to = "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+            max_priority_fee_per_gas = "0x1"
+
+            [[transactions]]
+            type = "l1"
+            to = "0x2b5ad5c4795c026514f8317c7a215e218dccd6cf"
+            value = "0x64"
+            serial_id = 7
+
+            [[transactions]]
+            type = "upgrade"
+            to = "0x000000000000000000000000000000000000800f"
+        "#;
+        let spec = parse_tx_spec(Path::new("spec.toml"), toml).unwrap();
+        let chain_id = L2ChainId::from(spec.chain_id);
+        let transactions = generate_transactions(&spec).unwrap();
+        assert_eq!(transactions.len(), 5);
+
+        assert_eq!(
+            test_key(0).address(),
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+                .parse()
+                .unwrap()
+        );
+        for (signer, tx) in transactions[..3].iter().enumerate() {
+            let address = test_key(signer as u32).address();
+            let l2_tx = L2Tx::try_from(tx.clone()).unwrap();
+            assert_eq!(l2_tx.initiator_account(), address);
+
+            // The signature is over the message that the node recomputes from the signed bytes.
+            let raw = l2_tx
+                .common_data
+                .input_data()
+                .expect("signed bytes are set");
+            let (request, hash) = TransactionRequest::from_bytes(raw, chain_id).unwrap();
+            assert_eq!(request.from, Some(address));
+            assert_eq!(hash, tx.hash());
+            let message = request.get_default_signed_message().unwrap();
+            let recovered = PackedEthSignature::deserialize_packed(&l2_tx.common_data.signature)
+                .unwrap()
+                .signature_recover_signer(&message)
+                .unwrap();
+            assert_eq!(recovered, address);
+
+            // Decoding the request again gives the same transaction.
+            let decoded = L2Tx::from_request(request, usize::MAX, true).unwrap();
+            assert_eq!(decoded.initiator_account(), address);
+            assert_eq!(decoded.nonce(), l2_tx.nonce());
+            assert_eq!(decoded.execute, l2_tx.execute);
+            assert_eq!(decoded.common_data.fee, l2_tx.common_data.fee);
+            assert_eq!(
+                decoded.common_data.transaction_type,
+                l2_tx.common_data.transaction_type
+            );
+        }
+        assert_eq!(transactions[1].nonce(), Some(Nonce(3)));
+        assert_eq!(transactions[2].nonce(), Some(Nonce(5)));
+
+        let l1_tx = &transactions[3];
+        assert_eq!(
+            l1_tx.hash(),
+            expected_canonical_tx_hash(255, 7.into(), l1_tx)
+        );
+        let upgrade_tx = &transactions[4];
+        assert_eq!(
+            upgrade_tx.hash(),
+            expected_canonical_tx_hash(
+                254,
+                (ProtocolVersionId::latest() as u16).into(),
+                upgrade_tx
+            )
+        );
+
+        // The default amount to mint overflows.
+        let toml = r#"
+            [[transactions]]
+            type = "upgrade"
+            gas_limit = "0x2"
+            max_fee_per_gas = "0x8000000000000000000000000000000000000000000000000000000000000000"
+        "#;
+        let spec = parse_tx_spec(Path::new("spec.toml"), toml).unwrap();
+        let err = generate_transactions(&spec).unwrap_err();
+        assert_eq!(
+            err,
+            "Transaction 0: `gas_limit * max_fee_per_gas + value` overflows, set `to_mint` explicitly"
+        );
+    }
+}
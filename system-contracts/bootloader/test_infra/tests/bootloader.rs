@@ -0,0 +1,61 @@
+//! Runs every `TEST_*` function of `bootloader_test.yul` as a separate `cargo test` case, so that the usual
+//! filtering (`cargo test --test bootloader -- safeSub`), `--exact`, `--list` and `--nocapture` work, and the
+//! IDEs can run a single test. Requires the compiled test bootloader (`yarn build:bootloader`).
+
+use std::process;
+use std::sync::Arc;
+
+use libtest_mimic::{Arguments, Failed, Trial};
+use test_infra::{
+    runner::TestContext,
+    suite::{SuiteOptions, TestSuite},
+};
+
+fn run_test(context: &TestContext, test_id: u32, nocapture: bool) -> Result<(), Failed> {
+    let outcome = context.run_test(test_id);
+    if nocapture {
+        for log in &outcome.logs {
+            println!("Test log {}", log);
+        }
+        let stats = &outcome.stats;
+        println!(
+            "{}: ergs: {}, cycles: {}, pubdata: {}, storage writes: {}",
+            outcome.test_name,
+            stats.ergs_used,
+            stats.cycles_used,
+            stats.pubdata_published,
+            stats.storage_writes
+        );
+    }
+    outcome.result.map_err(|error| {
+        // Without `--nocapture` the logs are only shown for the failed tests.
+        if nocapture || outcome.logs.is_empty() {
+            error.into()
+        } else {
+            format!("{}\nTest logs:\n  {}", error, outcome.logs.join("\n  ")).into()
+        }
+    })
+}
+
+fn main() {
+    let args = Arguments::from_args();
+
+    let suite = TestSuite::load(&SuiteOptions::default()).unwrap_or_else(|err| {
+        eprintln!("Failed to load the bootloader tests: {}", err);
+        process::exit(1);
+    });
+    let context = Arc::new(suite.context);
+
+    let nocapture = args.nocapture;
+    let trials = context
+        .test_names()
+        .into_iter()
+        .zip(1..)
+        .map(|(name, test_id)| {
+            let context = context.clone();
+            Trial::test(name, move || run_test(&context, test_id, nocapture))
+        })
+        .collect();
+
+    libtest_mimic::run(&args, trials).exit();
+}
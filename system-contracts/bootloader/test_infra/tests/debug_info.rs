@@ -0,0 +1,42 @@
+//! Checks the program counters of the compiled test bootloader against its assembly, by profiling a test that
+//! calls a function the optimizer can't inline. Requires the compiled test bootloader (`yarn build:bootloader`).
+
+use test_infra::{
+    fuzz::FuzzConfig,
+    suite::{SuiteOptions, TestSuite},
+};
+
+#[test]
+fn test_profile_resolves_not_inlined_function() {
+    let options = SuiteOptions {
+        profile: true,
+        fuzz: FuzzConfig {
+            runs: 1,
+            ..SuiteOptions::default().fuzz
+        },
+        ..SuiteOptions::default()
+    };
+    let suite = TestSuite::load(&options).unwrap();
+    let context = &suite.context;
+
+    let test_id = context
+        .test_names()
+        .iter()
+        .position(|name| name == "TEST_fuzz_lengthRoundedByWords")
+        .expect("Test not found") as u32
+        + 1;
+    let outcome = context.run_test(test_id);
+    assert_eq!(outcome.result, Ok(()));
+
+    // `testing_fuzzWord` reads the word via `$llvm_NoInline_llvm$_testing_hookResult`, which has its own
+    // label in the assembly. If the program counters were shifted, its frame would be attributed to
+    // another function (or none at all).
+    let profile = outcome.profile.unwrap();
+    assert!(
+        profile.stacks.keys().any(|stack| stack
+            .last()
+            .is_some_and(|function| function == "$llvm_NoInline_llvm$_testing_hookResult")),
+        "Function not found in the profile: {:?}",
+        profile.stacks.keys().collect::<Vec<_>>()
+    );
+}
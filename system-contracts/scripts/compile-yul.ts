@@ -13,8 +13,9 @@ const BOOTLOADER_DIR = "bootloader";
 const TIMESTAMP_FILE_YUL = "last_compilation_yul.timestamp";
 const TIMESTAMP_FILE_BOOTLOADER = "last_compilation_bootloader.timestamp";
 const LLVM_OPTIONS_FILE_EXTENSION = ".llvm.options";
+const TEST_BOOTLOADER_FILE = "bootloader_test.yul";
 
-export async function compileYul(paths: CompilerPaths, file: string) {
+export async function compileYul(paths: CompilerPaths, file: string, extraOptions = "") {
   const zksolcLocation = await compilerLocation(COMPILER_VERSION, IS_COMPILER_PRE_RELEASE);
 
   const filePath = `${paths.absolutePathSources}/${file}`;
@@ -29,16 +30,17 @@ export async function compileYul(paths: CompilerPaths, file: string) {
   }
 
   await spawn(
-    `${zksolcLocation} ${paths.absolutePathSources}/${file} --optimization 3 ${llvmOptions} --enable-eravm-extensions --yul --bin --overwrite -o ${paths.absolutePathArtifacts}`
+    `${zksolcLocation} ${paths.absolutePathSources}/${file} --optimization 3 ${llvmOptions} --enable-eravm-extensions --yul --bin ${extraOptions} --overwrite -o ${paths.absolutePathArtifacts}`
   );
 }
 
-export async function compileYulFolder(path: string) {
+export async function compileYulFolder(path: string, extraOptions = "", asmFiles: string[] = []) {
   const paths = prepareCompilerPaths(path);
   const files: string[] = (await fs.promises.readdir(path)).filter((fn) => fn.endsWith(".yul"));
   const promises: Promise<void>[] = [];
   for (const file of files) {
-    promises.push(compileYul(paths, `${file}`));
+    const options = asmFiles.includes(file) ? `${extraOptions} --asm` : extraOptions;
+    promises.push(compileYul(paths, `${file}`, options));
   }
   await Promise.all(promises);
 }
@@ -48,20 +50,25 @@ async function main() {
 
   program.version("0.1.0").name("compile yul").description("publish preimages for the L2 contracts");
 
-  program.command("compile-bootloader").action(async () => {
-    const timestampFilePath = _path.join(process.cwd(), TIMESTAMP_FILE_BOOTLOADER);
-    const folderToCheck = _path.join(process.cwd(), BOOTLOADER_DIR);
+  program
+    .command("compile-bootloader")
+    .option("--asm <files...>", "also emit the assembly of the given bootloaders, e.g. proved_batch.yul")
+    .action(async (options) => {
+      const timestampFilePath = _path.join(process.cwd(), TIMESTAMP_FILE_BOOTLOADER);
+      const folderToCheck = _path.join(process.cwd(), BOOTLOADER_DIR);
 
-    if (needsRecompilation(folderToCheck, timestampFilePath)) {
-      console.log("Compilation needed.");
-      await compileYulFolder("bootloader/build");
-      await compileYulFolder("bootloader/tests");
-      setCompilationTime(timestampFilePath);
-    } else {
-      console.log("Compilation not needed.");
-      return;
-    }
-  });
+      if (options.asm || needsRecompilation(folderToCheck, timestampFilePath)) {
+        console.log("Compilation needed.");
+        // The assembly is used by the bootloader test infra to map the executed instructions back to the source,
+        // so by default it is only emitted for the test bootloader.
+        await compileYulFolder("bootloader/build", "", [TEST_BOOTLOADER_FILE, ...(options.asm ?? [])]);
+        await compileYulFolder("bootloader/tests");
+        setCompilationTime(timestampFilePath);
+      } else {
+        console.log("Compilation not needed.");
+        return;
+      }
+    });
 
   program.command("compile-precompiles").action(async () => {
     const timestampFilePath = _path.join(process.cwd(), TIMESTAMP_FILE_YUL);
@@ -2,7 +2,7 @@
 import * as hre from "hardhat";
 
 import { ethers } from "ethers";
-import { existsSync, mkdirSync, writeFileSync, readFileSync } from "fs";
+import { existsSync, mkdirSync, writeFileSync, readFileSync, readdirSync } from "fs";
 import { render, renderFile } from "template-file";
 import { utils } from "zksync-ethers";
 import { getRevertSelector, getTransactionUtils } from "./constants";
@@ -144,6 +144,22 @@ function extractTestFunctionNames(sourceCode: string): string[] {
   return [...new Set(results)]; // Remove duplicates
 }
 
+// Yul string literals are limited to 32 bytes, so longer test names are written
+// into the test string buffer word by word and passed to the hook by pointer.
+function createTestStart(testName: string): string {
+  if (testName.length <= 32) {
+    return `testing_start("${testName}")`;
+  }
+
+  let testStart = "";
+  for (let offset = 0; offset < testName.length; offset += 32) {
+    testStart += `mstore(add(TEST_STRING_BUFFER_PTR(), ${offset}), "${testName.slice(offset, offset + 32)}")
+            `;
+  }
+  testStart += `testing_startString(TEST_STRING_BUFFER_PTR(), ${testName.length})`;
+  return testStart;
+}
+
 function createTestFramework(tests: string[]): string {
   let testFramework = `
     let test_id:= mload(0)
@@ -157,7 +173,7 @@ function createTestFramework(tests: string[]): string {
   tests.forEach((value, index) => {
     testFramework += `
         case ${index + 1} {
-            testing_start("${value}")
+            ${createTestStart(value)}
             ${value}()
         }
         `;
@@ -172,6 +188,34 @@ function createTestFramework(tests: string[]): string {
   return testFramework;
 }
 
+// Suffix that records the line of `bootloader.yul` that a line of the preprocessed bootloader comes from.
+const LINE_MARKER = / \/\/ bootloader\.yul:(\d+)$/;
+
+// Appends the line markers to the lines of the source. The preprocessor directives and the test code
+// placeholder are left as is, as they don't end up in the output.
+function addLineMarkers(source: string): string {
+  return source
+    .split("\n")
+    .map((line, index) =>
+      line.includes("<!--") || line.includes("{{CODE_START_PLACEHOLDER}}")
+        ? line
+        : `${line} // bootloader.yul:${index + 1}`
+    )
+    .join("\n");
+}
+
+// Removes the line markers, and returns the line of `bootloader.yul` for each line of the output
+// (`null` for the lines that are not from `bootloader.yul`, e.g. the tests).
+function stripLineMarkers(source: string): { source: string; lines: (number | null)[] } {
+  const lines: (number | null)[] = [];
+  const stripped = source.split("\n").map((line) => {
+    const match = line.match(LINE_MARKER);
+    lines.push(match ? Number(match[1]) : null);
+    return match ? line.slice(0, match.index) : line;
+  });
+  return { source: stripped.join("\n"), lines };
+}
+
 function validateSource(source: string) {
   const matches = source.matchAll(/<!-- @if BOOTLOADER_TYPE=='([^']*)' -->/g);
   for (const match of matches) {
@@ -223,11 +267,15 @@ async function main() {
 
   const bootloaderTestUtils = await renderFile("bootloader/tests/utils/test_utils.yul", {});
 
-  const bootloaderWithTests = await render(bootloaderSource, {
+  // The test bootloader is built with line markers, so that the test infra can map its lines back to
+  // `bootloader.yul` (e.g. for the coverage report).
+  const bootloaderWithTests = await render(addLineMarkers(bootloaderSource), {
     ...params,
     CODE_START_PLACEHOLDER: "\n" + bootloaderTestUtils + "\n" + bootloaderTests + "\n" + testFramework,
   });
-  const provedBootloaderWithTests = preprocess.preprocess(bootloaderWithTests, { BOOTLOADER_TYPE: "proved_batch" });
+  const { source: provedBootloaderWithTests, lines: bootloaderTestLines } = stripLineMarkers(
+    preprocess.preprocess(bootloaderWithTests, { BOOTLOADER_TYPE: "proved_batch" })
+  );
 
   if (!existsSync(OUTPUT_DIR_1)) {
     mkdirSync(OUTPUT_DIR_1);
@@ -237,24 +285,26 @@ async function main() {
     mkdirSync(OUTPUT_DIR_2);
   }
 
-  const transferTest = readFileSync("bootloader/tests/transfer_test.yul").toString();
-  const dummy = readFileSync("bootloader/tests/dummy.yul").toString();
-
   writeFileSync(`${OUTPUT_DIR_1}/bootloader_test.yul`, provedBootloaderWithTests);
   writeFileSync(`${OUTPUT_DIR_1}/proved_batch.yul`, provedBatchBootloader);
   writeFileSync(`${OUTPUT_DIR_1}/playground_batch.yul`, playgroundBatchBootloader);
   writeFileSync(`${OUTPUT_DIR_1}/gas_test.yul`, gasTestBootloader);
   writeFileSync(`${OUTPUT_DIR_1}/fee_estimate.yul`, feeEstimationBootloader);
-  writeFileSync(`${OUTPUT_DIR_1}/dummy.yul`, dummy);
-  writeFileSync(`${OUTPUT_DIR_1}/transfer_test.yul`, transferTest);
 
   writeFileSync(`${OUTPUT_DIR_2}/bootloader_test.yul`, provedBootloaderWithTests);
+  writeFileSync(`${OUTPUT_DIR_2}/bootloader_test.lines.json`, JSON.stringify(bootloaderTestLines));
   writeFileSync(`${OUTPUT_DIR_2}/proved_batch.yul`, provedBatchBootloader);
   writeFileSync(`${OUTPUT_DIR_2}/playground_batch.yul`, playgroundBatchBootloader);
   writeFileSync(`${OUTPUT_DIR_2}/gas_test.yul`, gasTestBootloader);
   writeFileSync(`${OUTPUT_DIR_2}/fee_estimate.yul`, feeEstimationBootloader);
-  writeFileSync(`${OUTPUT_DIR_2}/dummy.yul`, dummy);
-  writeFileSync(`${OUTPUT_DIR_2}/transfer_test.yul`, transferTest);
+
+  // Gas tests (e.g. `transfer_test.yul`) replace the whole bootloader, and are copied as is.
+  const gasTests = readdirSync("bootloader/tests").filter((file) => file.endsWith(".yul"));
+  for (const file of gasTests) {
+    const gasTest = readFileSync(`bootloader/tests/${file}`).toString();
+    writeFileSync(`${OUTPUT_DIR_1}/${file}`, gasTest);
+    writeFileSync(`${OUTPUT_DIR_2}/${file}`, gasTest);
+  }
 
   console.log("Bootloader preprocessing done!");
 }